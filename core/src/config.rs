@@ -28,6 +28,8 @@ pub struct TorConfig {
     pub data_dir: String,
     /// Enable logging
     pub enable_logging: bool,
+    /// Pluggable-transport bridges to try before falling back to a direct connection
+    pub bridges: Vec<BridgeConfig>,
 }
 
 impl Default for TorConfig {
@@ -38,10 +40,38 @@ impl Default for TorConfig {
             use_bundled: true,
             data_dir: "tor_data".to_string(),
             enable_logging: false,
+            bridges: Vec::new(),
         }
     }
 }
 
+/// Supported pluggable-transport kinds for reaching Tor from a censored network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PluggableTransport {
+    /// obfs4 - looks like random noise, the most widely deployed PT
+    Obfs4,
+    /// obfs3-style transport, kept for bridges that haven't upgraded yet
+    Obfs3,
+    /// meek/snowflake-style domain-fronting transport
+    Snowflake,
+}
+
+/// A single bridge line plus the parameters its pluggable transport needs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BridgeConfig {
+    /// Which pluggable transport this bridge speaks
+    pub transport: PluggableTransport,
+    /// "ip:port" the transport binary/relay listens on
+    pub address: String,
+    /// obfs4 cert parameter (base64), empty for transports that don't use one
+    pub cert: String,
+    /// obfs4 iat-mode parameter ("0", "1" or "2"), empty if not applicable
+    pub iat_mode: String,
+    /// Path to the pluggable-transport binary (e.g. `obfs4proxy`); with None
+    /// no transport is registered for this bridge (there is no in-process one)
+    pub transport_binary: Option<String>,
+}
+
 /// Pastebin configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PastebinConfig {
@@ -3,6 +3,7 @@
 
 use sodiumoxide::crypto::{
     kx, secretbox, pwhash, hash::sha256,
+    scalarmult::curve25519,
 };
 use sodiumoxide::utils::memzero;
 use sodiumoxide::randombytes::randombytes;
@@ -10,6 +11,12 @@ use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use log::error;
+use std::fmt;
+use std::sync::OnceLock;
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+mod wordlist;
 
 // Re-exportações para facilitar o uso
 pub use sodiumoxide::crypto::kx::{PublicKey, SecretKey, SessionKey};
@@ -31,20 +38,204 @@ pub enum CryptoError {
     KeyExchangeError,
     #[error("Invalid fingerprint")]
     InvalidFingerprint,
+    #[error("Invalid mnemonic: {0}")]
+    InvalidMnemonic(String),
+    #[error("Compression error: {0}")]
+    CompressionError(String),
+    #[error("Decompressed size exceeds the configured limit")]
+    DecompressionBombRejected,
+    #[error("Unsupported KDF: {0}")]
+    UnsupportedKdf(String),
+}
+
+/// Zera o conteúdo sensível de `T` em memória. Implementado para os poucos
+/// tipos que efetivamente guardam segredos neste módulo, em vez de depender
+/// de uma crate externa de zeroização.
+pub trait SecureErase {
+    fn secure_erase(&mut self);
+}
+
+impl SecureErase for String {
+    fn secure_erase(&mut self) {
+        unsafe {
+            memzero(self.as_mut_vec());
+        }
+        self.clear();
+    }
+}
+
+impl SecureErase for Vec<u8> {
+    fn secure_erase(&mut self) {
+        memzero(self);
+        self.clear();
+    }
+}
+
+impl SecureErase for [u8; secretbox::KEYBYTES] {
+    fn secure_erase(&mut self) {
+        memzero(self);
+    }
+}
+
+impl SecureErase for SessionKey {
+    fn secure_erase(&mut self) {
+        // `SessionKey` já zera seus bytes no próprio `Drop` do sodiumoxide;
+        // substituímos o conteúdo por zeros desde já para reduzir a janela
+        // em que o segredo original permanece legível na memória.
+        if let Some(zeroed) = SessionKey::from_slice(&vec![0u8; self.as_ref().len()]) {
+            *self = zeroed;
+        }
+    }
+}
+
+/// Wrapper que zera o segredo ao sair de escopo e nunca o expõe em
+/// `Debug`/`Display`, para que chaves privadas não acabem em logs nem
+/// sobrevivam no heap após o uso.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Secret<T: SecureErase>(T);
+
+impl<T: SecureErase> Secret<T> {
+    pub fn new(inner: T) -> Self {
+        Self(inner)
+    }
+
+    /// Acesso explícito ao valor protegido. Use apenas quando for realmente
+    /// necessário (ex.: selar a chave com uma senha antes de gravá-la em disco).
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Acesso mutável ao valor protegido (ex.: derivar uma chave em um buffer
+    /// que já nasceu dentro do wrapper, para que ele seja zerado ao sair de escopo).
+    pub fn expose_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: SecureErase> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***REDACTED***)")
+    }
+}
+
+impl<T: SecureErase> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "***REDACTED***")
+    }
+}
+
+impl<T: SecureErase> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.secure_erase();
+    }
+}
+
+/// Newtype para senhas fornecidas pelo usuário. Garante que a senha nunca
+/// apareça em `Debug`/`Display` (logs, `eprintln!` de erros, etc.) e que seja
+/// zerada da memória assim que sair de escopo. É o único tipo aceito pelas
+/// APIs de criptografia baseadas em senha.
+#[derive(Clone)]
+pub struct SafePassword(Secret<String>);
+
+impl SafePassword {
+    pub fn new(password: String) -> Self {
+        Self(Secret::new(password))
+    }
+
+    /// Acesso explícito à senha em texto claro, necessário para alimentar o KDF.
+    pub fn expose(&self) -> &str {
+        self.0.expose()
+    }
+
+    /// Lê uma linha de stdin diretamente para dentro do wrapper zeroizante,
+    /// sem deixar a senha em uma `String` solta fora dele.
+    pub fn from_stdin() -> std::io::Result<Self> {
+        let mut buf = String::new();
+        std::io::stdin().read_line(&mut buf)?;
+        while buf.ends_with('\n') || buf.ends_with('\r') {
+            buf.pop();
+        }
+        Ok(Self(Secret::new(buf)))
+    }
+
+    /// Lê a senha de uma variável de ambiente (útil para automação/CI).
+    pub fn from_env(var: &str) -> Result<Self, std::env::VarError> {
+        std::env::var(var).map(|v| Self(Secret::new(v)))
+    }
+}
+
+impl fmt::Debug for SafePassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SafePassword(***REDACTED***)")
+    }
+}
+
+impl fmt::Display for SafePassword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "***REDACTED***")
+    }
 }
 
 /// Par de chaves para identidade (X25519)
-#[derive(Clone, Debug, Serialize, Deserialize)]
+///
+/// Não deriva `Serialize`/`Deserialize`: `secret_key` é a chave privada em
+/// claro, e qualquer `serde_json::to_string` feito de fora deste módulo
+/// (logging, um endpoint de debug, etc.) a gravaria sem criptografia
+/// nenhuma. A única serialização legítima é a do envelope selado por
+/// `Crypto::seal_identity`/`open_identity`, então ela passa por
+/// `to_sealed_json`/`from_sealed_json` abaixo em vez do derive.
+#[derive(Clone, Debug)]
 pub struct IdentityKeyPair {
-    pub public_key: String,   // base64
-    pub secret_key: String,   // base64 (deve ser mantido em segredo)
+    pub public_key: String,        // base64
+    pub secret_key: Secret<String>, // base64 (deve ser mantido em segredo)
+}
+
+/// Espelho serializável de `IdentityKeyPair`, usado apenas para produzir o
+/// JSON em claro que é imediatamente selado por `Crypto::seal_identity` (ou
+/// lido de dentro de um envelope já aberto por `open_identity`). Não é
+/// público: o único jeito de serializar/desserializar um `IdentityKeyPair`
+/// de fora deste módulo é por `to_sealed_json`/`from_sealed_json`.
+#[derive(Serialize, Deserialize)]
+struct RawIdentityKeyPair {
+    public_key: String,
+    secret_key: String,
+}
+
+impl IdentityKeyPair {
+    /// Serializa para o JSON em claro que `SecureStorage` sela em disco.
+    /// Não gravar o resultado sem antes passar por `Crypto::seal_identity`.
+    pub(crate) fn to_sealed_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&RawIdentityKeyPair {
+            public_key: self.public_key.clone(),
+            secret_key: self.secret_key.expose().clone(),
+        })
+    }
+
+    /// Reconstrói o par de chaves a partir do JSON em claro obtido depois de
+    /// abrir o envelope selado com `Crypto::open_identity`.
+    pub(crate) fn from_sealed_json(data: &[u8]) -> Result<Self, serde_json::Error> {
+        let raw: RawIdentityKeyPair = serde_json::from_slice(data)?;
+        Ok(Self {
+            public_key: raw.public_key,
+            secret_key: Secret::new(raw.secret_key),
+        })
+    }
 }
 
 /// Chaves de sessão derivadas do key exchange (rx e tx)
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct SessionKeys {
-    pub rx: SessionKey,
-    pub tx: SessionKey,
+    pub rx: Secret<SessionKey>,
+    pub tx: Secret<SessionKey>,
+}
+
+impl fmt::Debug for SessionKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionKeys")
+            .field("rx", &self.rx)
+            .field("tx", &self.tx)
+            .finish()
+    }
 }
 
 /// Representação amigável da impressão digital (fingerprint) de uma chave pública
@@ -90,6 +281,88 @@ pub struct EncryptedMessage {
     pub ciphertext: String,  // base64
 }
 
+/// Cabeçalho de um fluxo cifrado em blocos (estilo RFC 8188), carregando o
+/// salt usado para derivar as chaves do fluxo e o tamanho de registro usado
+/// para fragmentar o texto claro.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StreamHeader {
+    pub salt: String, // base64, 16 bytes
+    pub record_size: usize,
+}
+
+/// Versão do cabeçalho compress-then-encrypt (byte 0 do blob decifrado em
+/// `encrypt_compressed`/`encrypt_with_password_compressed`).
+const COMPRESSED_FORMAT_VERSION: u8 = 1;
+
+/// Identificador do KDF usado para selar uma `SecureIdentity` (ver
+/// `storage.rs`). Guardado como string em vez de enum fechado para que um
+/// envelope futuro com outro `kdf_id` ainda seja reconhecível no disco,
+/// mesmo que esta versão da crate ainda não saiba abri-lo.
+pub const KDF_ID_ARGON2ID: &str = "argon2id13";
+
+/// Parâmetros de custo do Argon2id usados para selar uma identidade,
+/// guardados junto ao ciphertext em `SecureIdentity` em vez de embutidos
+/// (invisíveis) em `encrypt_with_password`. Isso permite abrir identidades
+/// antigas seladas com um custo menor e, quando o fator de trabalho
+/// recomendado subir, reselar com parâmetros novos sem invalidar o arquivo
+/// atual (ver `SecureStorage::rehash_if_needed`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub kdf_id: String,
+    pub salt: String, // base64, 16 bytes
+    pub ops_limit: usize,
+    pub mem_limit: usize,
+    /// Grau de paralelismo do Argon2id usado. Fixo em 1, já que é o único
+    /// valor suportado por `pwhash::argon2id13`; ainda assim é guardado para
+    /// que o envelope seja autodescritivo caso um `kdf_id` futuro suporte
+    /// mais de uma lane.
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Parâmetros recomendados por esta versão da crate, com um salt novo.
+    fn current(salt_bytes: [u8; 16]) -> Self {
+        Self {
+            kdf_id: KDF_ID_ARGON2ID.to_string(),
+            salt: BASE64.encode(salt_bytes),
+            ops_limit: pwhash::argon2id13::OPSLIMIT_INTERACTIVE.0,
+            mem_limit: pwhash::argon2id13::MEMLIMIT_INTERACTIVE.0,
+            parallelism: 1,
+        }
+    }
+
+    /// Estes parâmetros já atendem (ou superam) o custo recomendado pela
+    /// versão atual da crate? Usado por `SecureStorage::rehash_if_needed`
+    /// para decidir se uma identidade precisa ser reselada.
+    pub fn meets_current_cost(&self) -> bool {
+        self.kdf_id == KDF_ID_ARGON2ID
+            && self.ops_limit >= pwhash::argon2id13::OPSLIMIT_INTERACTIVE.0
+            && self.mem_limit >= pwhash::argon2id13::MEMLIMIT_INTERACTIVE.0
+    }
+
+    fn derive_key(&self, password: &SafePassword) -> Result<Secret<[u8; secretbox::KEYBYTES]>, CryptoError> {
+        if self.kdf_id != KDF_ID_ARGON2ID {
+            return Err(CryptoError::UnsupportedKdf(self.kdf_id.clone()));
+        }
+
+        let salt_bytes = BASE64.decode(&self.salt)
+            .map_err(|e| CryptoError::Base64Error(e.to_string()))?;
+        let salt = pwhash::argon2id13::Salt::from_slice(&salt_bytes)
+            .ok_or(CryptoError::DecryptionFailed)?;
+
+        let mut key = Secret::new([0u8; secretbox::KEYBYTES]);
+        pwhash::argon2id13::derive_key(
+            key.expose_mut(),
+            password.expose().as_bytes(),
+            &salt,
+            pwhash::argon2id13::OpsLimit(self.ops_limit),
+            pwhash::argon2id13::MemLimit(self.mem_limit),
+        ).map_err(|_| CryptoError::DecryptionFailed)?;
+
+        Ok(key)
+    }
+}
+
 /// Estrutura principal de operações criptográficas (stateless)
 pub struct Crypto;
 
@@ -112,8 +385,81 @@ impl Crypto {
         let (pk, sk) = kx::gen_keypair();
         IdentityKeyPair {
             public_key: BASE64.encode(pk.as_ref()),
-            secret_key: BASE64.encode(sk.as_ref()),
+            secret_key: Secret::new(BASE64.encode(sk.as_ref())),
+        }
+    }
+
+    /// Gera uma frase mnemônica (24 palavras) a partir da chave secreta de
+    /// identidade, no mesmo espírito do BIP39: entropia (256 bits) + checksum
+    /// (8 bits, primeiro byte do SHA-256 da entropia) agrupados em blocos de
+    /// 11 bits que indexam a wordlist fixa.
+    pub fn identity_to_mnemonic(keypair: &IdentityKeyPair) -> Result<Vec<String>, CryptoError> {
+        let entropy = BASE64.decode(keypair.secret_key.expose())
+            .map_err(|e| CryptoError::Base64Error(e.to_string()))?;
+        if entropy.len() != 32 {
+            return Err(CryptoError::InvalidKeyLength);
+        }
+
+        let checksum_byte = sha256::hash(&entropy).as_ref()[0];
+
+        let mut bits = Vec::with_capacity(entropy.len() * 8 + 8);
+        for byte in &entropy {
+            push_bits(&mut bits, *byte, 8);
+        }
+        push_bits(&mut bits, checksum_byte, 8);
+
+        let words = wordlist::words();
+        let mnemonic = bits
+            .chunks(11)
+            .map(|chunk| {
+                let index = bits_to_u16(chunk);
+                words[index as usize].clone()
+            })
+            .collect();
+
+        Ok(mnemonic)
+    }
+
+    /// Reconstrói um `IdentityKeyPair` a partir de uma frase mnemônica gerada
+    /// por `identity_to_mnemonic`, verificando o checksum e re-derivando a
+    /// chave pública a partir da entropia recuperada.
+    pub fn identity_from_mnemonic(phrase: &[String]) -> Result<IdentityKeyPair, CryptoError> {
+        if phrase.len() != 24 {
+            return Err(CryptoError::InvalidMnemonic(
+                format!("expected 24 words, got {}", phrase.len())
+            ));
+        }
+
+        let mut bits = Vec::with_capacity(264);
+        for word in phrase {
+            let index = wordlist::index_of(word)
+                .ok_or_else(|| CryptoError::InvalidMnemonic(format!("unknown word: {word}")))?;
+            push_bits(&mut bits, (index >> 8) as u8, 3);
+            push_bits(&mut bits, (index & 0xFF) as u8, 8);
         }
+
+        let entropy_bits = &bits[..256];
+        let checksum_bits = &bits[256..264];
+
+        let mut entropy = [0u8; 32];
+        for (i, byte_bits) in entropy_bits.chunks(8).enumerate() {
+            entropy[i] = bits_to_u16(byte_bits) as u8;
+        }
+        let checksum_byte = bits_to_u16(checksum_bits) as u8;
+
+        let expected_checksum = sha256::hash(&entropy).as_ref()[0];
+        if checksum_byte != expected_checksum {
+            return Err(CryptoError::InvalidMnemonic("checksum mismatch".to_string()));
+        }
+
+        let secret = curve25519::Scalar::from_slice(&entropy)
+            .ok_or(CryptoError::InvalidKeyLength)?;
+        let public = curve25519::scalarmult_base(&secret);
+
+        Ok(IdentityKeyPair {
+            public_key: BASE64.encode(public.as_ref()),
+            secret_key: Secret::new(BASE64.encode(&entropy)),
+        })
     }
 
     /// Deriva as chaves de sessão do lado do cliente (initiator)
@@ -123,7 +469,7 @@ impl Crypto {
         server_pk: &PublicKey,
     ) -> Result<SessionKeys, CryptoError> {
         kx::client_session_keys(client_pk, client_sk, server_pk)
-            .map(|(rx, tx)| SessionKeys { rx, tx })
+            .map(|(rx, tx)| SessionKeys { rx: Secret::new(rx), tx: Secret::new(tx) })
             .map_err(|_| CryptoError::KeyExchangeError)
     }
 
@@ -134,10 +480,76 @@ impl Crypto {
         client_pk: &PublicKey,
     ) -> Result<SessionKeys, CryptoError> {
         kx::server_session_keys(server_pk, server_sk, client_pk)
-            .map(|(rx, tx)| SessionKeys { rx, tx })
+            .map(|(rx, tx)| SessionKeys { rx: Secret::new(rx), tx: Secret::new(tx) })
             .map_err(|_| CryptoError::KeyExchangeError)
     }
 
+    /// Calcula o segredo X25519 estático entre a chave secreta de identidade
+    /// local e a chave pública de identidade de longo prazo do par. Junto com
+    /// `derive_authenticated_session_keys`, amarra uma sessão à identidade de
+    /// ambos os lados além do par efêmero que já garante forward secrecy: só
+    /// quem possui a chave secreta correspondente reproduz este segredo.
+    pub fn static_shared_secret(
+        identity: &IdentityKeyPair,
+        peer_identity_public_key: &PublicKey,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let secret_bytes = BASE64.decode(identity.secret_key.expose())
+            .map_err(|e| CryptoError::Base64Error(e.to_string()))?;
+        let scalar = curve25519::Scalar::from_slice(&secret_bytes)
+            .ok_or(CryptoError::InvalidKeyLength)?;
+        let point = curve25519::GroupElement::from_slice(peer_identity_public_key.as_ref())
+            .ok_or(CryptoError::InvalidKeyLength)?;
+        let shared = curve25519::scalarmult(&scalar, &point)
+            .map_err(|_| CryptoError::KeyExchangeError)?;
+        Ok(shared.as_ref().to_vec())
+    }
+
+    /// Mistura as chaves de sessão efêmeras (já derivadas via
+    /// `client_session_keys`/`server_session_keys`) com o segredo estático de
+    /// `static_shared_secret` através de HKDF-SHA256, produzindo as chaves
+    /// finais de uma sessão autenticada. Os nonces trocados no handshake
+    /// (`client_nonce`, sempre o do lado que manda primeiro, e
+    /// `server_nonce`) entram na `info` do HKDF, amarrando as chaves
+    /// derivadas à transcrição daquele handshake específico: reusar os
+    /// mesmos pares de chave efêmera/estática com nonces diferentes produz
+    /// chaves de sessão diferentes.
+    ///
+    /// rx e tx usam exatamente a mesma `info`: a separação por direção já
+    /// vem do kx (`ephemeral.rx`/`ephemeral.tx` são valores distintos, com
+    /// `client.tx == server.rx` e vice-versa), então rotular cada lado com
+    /// uma `info` diferente faria o lado que manda e o lado que recebe
+    /// derivarem chaves diferentes a partir do mesmo segredo — exatamente o
+    /// que `Crypto::decrypt` precisa que não aconteça.
+    pub fn derive_authenticated_session_keys(
+        ephemeral: &SessionKeys,
+        static_secret: &[u8],
+        client_nonce: &[u8],
+        server_nonce: &[u8],
+    ) -> SessionKeys {
+        let mut info = b"torpaste-auth-session".to_vec();
+        info.extend_from_slice(client_nonce);
+        info.extend_from_slice(server_nonce);
+
+        let rx = derive_authenticated_key(ephemeral.rx.expose(), static_secret, &info);
+        let tx = derive_authenticated_key(ephemeral.tx.expose(), static_secret, &info);
+        SessionKeys { rx: Secret::new(rx), tx: Secret::new(tx) }
+    }
+
+    /// Deriva, via HKDF-SHA256, a chave de dados usada para selar o cofre de
+    /// contatos (`contacts.enc`) a partir da chave secreta de identidade. Cada
+    /// segredo persistido tem sua própria chave derivada (esta é independente
+    /// da usada por `encrypt_with_password` para a própria identidade e das
+    /// chaves de sessão de handshake), então comprometer uma não compromete as outras.
+    pub fn derive_contacts_key(identity: &IdentityKeyPair) -> Result<SessionKey, CryptoError> {
+        let secret_bytes = BASE64.decode(identity.secret_key.expose())
+            .map_err(|e| CryptoError::Base64Error(e.to_string()))?;
+        let hk = Hkdf::<Sha256>::new(None, &secret_bytes);
+        let mut bytes = [0u8; secretbox::KEYBYTES];
+        hk.expand(b"torpaste-contacts-key-v1", &mut bytes)
+            .expect("HKDF output length is always valid for secretbox::KEYBYTES");
+        SessionKey::from_slice(&bytes).ok_or(CryptoError::InvalidKeyLength)
+    }
+
     /// Criptografa uma mensagem usando a chave de sessão (rx ou tx) e um nonce aleatório
     pub fn encrypt(message: &[u8], key: &SessionKey) -> EncryptedMessage {
         let nonce = secretbox::gen_nonce();
@@ -164,6 +576,99 @@ impl Crypto {
             .map_err(|_| CryptoError::DecryptionFailed)
     }
 
+    /// Cifra `plaintext` como um fluxo de registros independentes (estilo
+    /// RFC 8188), em vez de um único `secretbox` monolítico. Recebe o texto
+    /// claro inteiro de uma vez e devolve todos os registros cifrados juntos
+    /// em um `Vec`, então o texto claro e o ciphertext completo ficam em
+    /// memória ao mesmo tempo; cada registro é selado isoladamente.
+    ///
+    /// Deriva uma content-encryption key e um nonce base da chave de sessão
+    /// via HKDF-SHA256 sobre um salt aleatório de 16 bytes, fragmenta o
+    /// texto claro em registros de `record_size` bytes e sela cada um com o
+    /// nonce base XORado com um contador big-endian de 64 bits. Cada
+    /// registro recebe um byte delimitador (`0x01` não-final, `0x02` final)
+    /// para que truncamentos sejam detectáveis na descriptografia.
+    pub fn encrypt_stream(
+        plaintext: &[u8],
+        key: &SessionKey,
+        record_size: usize,
+    ) -> (StreamHeader, Vec<EncryptedMessage>) {
+        let salt = randombytes(16);
+        let (cek, base_nonce) = derive_stream_keys(key, &salt);
+
+        let chunks: Vec<&[u8]> = if plaintext.is_empty() {
+            vec![&[]]
+        } else {
+            plaintext.chunks(record_size).collect()
+        };
+        let last = chunks.len() - 1;
+
+        let records = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut record = chunk.to_vec();
+                record.push(if i == last { 0x02 } else { 0x01 });
+
+                let nonce = record_nonce(&base_nonce, i as u64);
+                let ciphertext = secretbox::seal(&record, &nonce, &cek);
+                EncryptedMessage {
+                    nonce: BASE64.encode(nonce.as_ref()),
+                    ciphertext: BASE64.encode(&ciphertext),
+                }
+            })
+            .collect();
+
+        let header = StreamHeader {
+            salt: BASE64.encode(&salt),
+            record_size,
+        };
+        (header, records)
+    }
+
+    /// Reverte `encrypt_stream`: reabre cada registro na ordem, valida o
+    /// byte delimitador (detectando truncamento caso o último registro não
+    /// carregue o delimitador final, ou caso um delimitador final apareça
+    /// cedo demais) e concatena o texto claro recuperado.
+    pub fn decrypt_stream(
+        header: &StreamHeader,
+        records: &[EncryptedMessage],
+        key: &SessionKey,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let salt = BASE64.decode(&header.salt)
+            .map_err(|e| CryptoError::Base64Error(e.to_string()))?;
+        let (cek, base_nonce) = derive_stream_keys(key, &salt);
+
+        let mut out = Vec::new();
+        let last = records.len().saturating_sub(1);
+
+        for (i, record) in records.iter().enumerate() {
+            let expected_nonce = record_nonce(&base_nonce, i as u64);
+            let nonce = SecretBoxNonce::from_slice(
+                &BASE64.decode(&record.nonce).map_err(|e| CryptoError::Base64Error(e.to_string()))?
+            ).ok_or(CryptoError::InvalidKeyLength)?;
+            if nonce != expected_nonce {
+                return Err(CryptoError::DecryptionFailed);
+            }
+
+            let ciphertext = BASE64.decode(&record.ciphertext)
+                .map_err(|e| CryptoError::Base64Error(e.to_string()))?;
+            let mut plain = secretbox::open(&ciphertext, &nonce, &cek)
+                .map_err(|_| CryptoError::DecryptionFailed)?;
+
+            let delimiter = plain.pop().ok_or(CryptoError::DecryptionFailed)?;
+            let is_final = i == last;
+            match (delimiter, is_final) {
+                (0x01, false) | (0x02, true) => {}
+                _ => return Err(CryptoError::DecryptionFailed), // truncado ou fora de ordem
+            }
+
+            out.extend_from_slice(&plain);
+        }
+
+        Ok(out)
+    }
+
     /// Gera bytes aleatórios
     pub fn random_bytes(len: usize) -> Vec<u8> {
         randombytes(len)
@@ -179,24 +684,78 @@ impl Crypto {
         memzero(data);
     }
 
+    /// Comprime `message` com zstd e então sela o resultado, economizando
+    /// banda sobre os circuitos Tor já lentos. O cabeçalho grava a versão do
+    /// formato e o tamanho original (antes da compressão) para que blobs
+    /// antigos continuem legíveis e para permitir rejeitar "bombas" de
+    /// descompressão antes de alocar o buffer de saída.
+    pub fn encrypt_compressed(message: &[u8], key: &SessionKey) -> Result<EncryptedMessage, CryptoError> {
+        let compressed = zstd::bulk::compress(message, 0)
+            .map_err(|e| CryptoError::CompressionError(e.to_string()))?;
+
+        let mut framed = Vec::with_capacity(9 + compressed.len());
+        framed.push(COMPRESSED_FORMAT_VERSION);
+        framed.extend_from_slice(&(message.len() as u64).to_be_bytes());
+        framed.extend_from_slice(&compressed);
+
+        Ok(Self::encrypt(&framed, key))
+    }
+
+    /// Reverte `encrypt_compressed`. `max_uncompressed_size` deve vir de
+    /// `ProtocolConfig::max_message_size`: blobs que declaram um tamanho
+    /// original maior são rejeitados antes mesmo de tentar descomprimir.
+    pub fn decrypt_compressed(
+        enc: &EncryptedMessage,
+        key: &SessionKey,
+        max_uncompressed_size: usize,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let framed = Self::decrypt(enc, key)?;
+        decompress_framed(&framed, max_uncompressed_size)
+    }
+
+    /// Variante de `encrypt_with_password` que comprime os dados com zstd
+    /// antes de selar, usando o mesmo cabeçalho versionado de `encrypt_compressed`.
+    pub fn encrypt_with_password_compressed(data: &[u8], password: &SafePassword) -> Result<Vec<u8>, CryptoError> {
+        let compressed = zstd::bulk::compress(data, 0)
+            .map_err(|e| CryptoError::CompressionError(e.to_string()))?;
+
+        let mut framed = Vec::with_capacity(9 + compressed.len());
+        framed.push(COMPRESSED_FORMAT_VERSION);
+        framed.extend_from_slice(&(data.len() as u64).to_be_bytes());
+        framed.extend_from_slice(&compressed);
+
+        Self::encrypt_with_password(&framed, password)
+    }
+
+    /// Reverte `encrypt_with_password_compressed`.
+    pub fn decrypt_with_password_compressed(
+        data: &[u8],
+        password: &SafePassword,
+        max_uncompressed_size: usize,
+    ) -> Result<Vec<u8>, CryptoError> {
+        let framed = Self::decrypt_with_password(data, password)
+            .map_err(|_| CryptoError::DecryptionFailed)?;
+        decompress_framed(&framed, max_uncompressed_size)
+    }
+
     /// Criptografa dados com uma chave derivada de senha (usado para armazenar identidade)
-    pub fn encrypt_with_password(data: &[u8], password: &str) -> Result<Vec<u8>, CryptoError> {
+    pub fn encrypt_with_password(data: &[u8], password: &SafePassword) -> Result<Vec<u8>, CryptoError> {
         // Gera salt aleatório de 16 bytes
         let salt_bytes = randombytes(16);
         let salt = pwhash::argon2id13::Salt::from_slice(&salt_bytes)
             .ok_or(CryptoError::EncryptionFailed)?;
 
         // Deriva chave de 32 bytes usando Argon2id (parâmetros interativos)
-        let mut key = [0u8; secretbox::KEYBYTES];
+        let mut key = Secret::new([0u8; secretbox::KEYBYTES]);
         pwhash::argon2id13::derive_key(
-            &mut key,
-            password.as_bytes(),
+            key.expose_mut(),
+            password.expose().as_bytes(),
             &salt,
             pwhash::argon2id13::OPSLIMIT_INTERACTIVE,
             pwhash::argon2id13::MEMLIMIT_INTERACTIVE,
         ).map_err(|_| CryptoError::EncryptionFailed)?;
 
-        let key_box = secretbox::Key::from_slice(&key).unwrap();
+        let key_box = secretbox::Key::from_slice(key.expose()).unwrap();
 
         // Gera nonce e cifra
         let nonce = secretbox::gen_nonce();
@@ -211,7 +770,7 @@ impl Crypto {
     }
 
     /// Descriptografa dados com senha (formato gerado por encrypt_with_password)
-    pub fn decrypt_with_password(data: &[u8], password: &str) -> Result<Vec<u8>, CryptoError> {
+    pub fn decrypt_with_password(data: &[u8], password: &SafePassword) -> Result<Vec<u8>, CryptoError> {
         if data.len() < 16 + 24 {
             return Err(CryptoError::DecryptionFailed);
         }
@@ -223,16 +782,16 @@ impl Crypto {
         let salt = pwhash::argon2id13::Salt::from_slice(salt_bytes)
             .ok_or(CryptoError::DecryptionFailed)?;
 
-        let mut key = [0u8; secretbox::KEYBYTES];
+        let mut key = Secret::new([0u8; secretbox::KEYBYTES]);
         pwhash::argon2id13::derive_key(
-            &mut key,
-            password.as_bytes(),
+            key.expose_mut(),
+            password.expose().as_bytes(),
             &salt,
             pwhash::argon2id13::OPSLIMIT_INTERACTIVE,
             pwhash::argon2id13::MEMLIMIT_INTERACTIVE,
         ).map_err(|_| CryptoError::DecryptionFailed)?;
 
-        let key_box = secretbox::Key::from_slice(&key).unwrap();
+        let key_box = secretbox::Key::from_slice(key.expose()).unwrap();
 
         let nonce = SecretBoxNonce::from_slice(nonce_bytes)
             .ok_or(CryptoError::DecryptionFailed)?;
@@ -240,6 +799,46 @@ impl Crypto {
         secretbox::open(ciphertext, &nonce, &key_box)
             .map_err(|_| CryptoError::DecryptionFailed)
     }
+
+    /// Sela os dados de uma identidade (`SecureIdentity`) em um envelope
+    /// autodescritivo: os parâmetros de Argon2id usados viajam junto como
+    /// `KdfParams`, em vez de ficarem fixos e invisíveis no código como em
+    /// `encrypt_with_password`. Isso permite que `SecureStorage::rehash_if_needed`
+    /// detecte identidades seladas com um custo abaixo do atual e as resele.
+    pub fn seal_identity(data: &[u8], password: &SafePassword) -> Result<(KdfParams, Vec<u8>), CryptoError> {
+        let salt_bytes: [u8; 16] = randombytes(16)
+            .try_into()
+            .map_err(|_| CryptoError::EncryptionFailed)?;
+        let params = KdfParams::current(salt_bytes);
+        let key = params.derive_key(password)?;
+        let key_box = secretbox::Key::from_slice(key.expose()).unwrap();
+
+        let nonce = secretbox::gen_nonce();
+        let ciphertext = secretbox::seal(data, &nonce, &key_box);
+
+        let mut out = Vec::with_capacity(secretbox::NONCEBYTES + ciphertext.len());
+        out.extend_from_slice(nonce.as_ref());
+        out.extend_from_slice(&ciphertext);
+        Ok((params, out))
+    }
+
+    /// Reverte `seal_identity`, derivando a chave com os parâmetros gravados
+    /// em `params` (e não com os parâmetros atuais da crate), para que
+    /// identidades seladas no passado com um custo menor continuem abrindo.
+    pub fn open_identity(params: &KdfParams, sealed: &[u8], password: &SafePassword) -> Result<Vec<u8>, CryptoError> {
+        if sealed.len() < secretbox::NONCEBYTES {
+            return Err(CryptoError::DecryptionFailed);
+        }
+        let key = params.derive_key(password)?;
+        let key_box = secretbox::Key::from_slice(key.expose()).unwrap();
+
+        let (nonce_bytes, ciphertext) = sealed.split_at(secretbox::NONCEBYTES);
+        let nonce = SecretBoxNonce::from_slice(nonce_bytes)
+            .ok_or(CryptoError::DecryptionFailed)?;
+
+        secretbox::open(ciphertext, &nonce, &key_box)
+            .map_err(|_| CryptoError::DecryptionFailed)
+    }
 }
 
 impl Default for Crypto {
@@ -248,6 +847,87 @@ impl Default for Crypto {
     }
 }
 
+/// Empilha os `n` bits menos significativos de `byte` (MSB primeiro) em `bits`.
+fn push_bits(bits: &mut Vec<bool>, byte: u8, n: u8) {
+    for i in (0..n).rev() {
+        bits.push((byte >> i) & 1 == 1);
+    }
+}
+
+/// Reconstrói um inteiro a partir de uma sequência de bits (MSB primeiro).
+fn bits_to_u16(bits: &[bool]) -> u16 {
+    bits.iter().fold(0u16, |acc, &bit| (acc << 1) | (bit as u16))
+}
+
+/// Deriva a content-encryption key e o nonce base de um fluxo cifrado a
+/// partir da chave de sessão e de um salt aleatório, via HKDF-SHA256.
+fn derive_stream_keys(key: &SessionKey, salt: &[u8]) -> (SecretBoxKey, [u8; secretbox::NONCEBYTES]) {
+    let hk = Hkdf::<Sha256>::new(Some(salt), key.as_ref());
+
+    let mut cek_bytes = [0u8; secretbox::KEYBYTES];
+    hk.expand(b"torpaste-stream-cek", &mut cek_bytes)
+        .expect("HKDF output length is always valid for secretbox::KEYBYTES");
+
+    let mut nonce_bytes = [0u8; secretbox::NONCEBYTES];
+    hk.expand(b"torpaste-stream-nonce", &mut nonce_bytes)
+        .expect("HKDF output length is always valid for secretbox::NONCEBYTES");
+
+    let cek = SecretBoxKey::from_slice(&cek_bytes).expect("cek has correct length");
+    (cek, nonce_bytes)
+}
+
+/// Lê o cabeçalho compress-then-encrypt (versão + tamanho original) e
+/// descomprime o restante, rejeitando o blob se o tamanho declarado
+/// ultrapassar `max_uncompressed_size` (defesa contra bombas de descompressão).
+fn decompress_framed(framed: &[u8], max_uncompressed_size: usize) -> Result<Vec<u8>, CryptoError> {
+    if framed.len() < 9 {
+        return Err(CryptoError::DecryptionFailed);
+    }
+
+    let version = framed[0];
+    if version != COMPRESSED_FORMAT_VERSION {
+        return Err(CryptoError::CompressionError(format!("unsupported format version {version}")));
+    }
+
+    let uncompressed_len = u64::from_be_bytes(framed[1..9].try_into().unwrap()) as usize;
+    if uncompressed_len > max_uncompressed_size {
+        return Err(CryptoError::DecompressionBombRejected);
+    }
+
+    let compressed = &framed[9..];
+    let decompressed = zstd::bulk::decompress(compressed, max_uncompressed_size)
+        .map_err(|e| CryptoError::CompressionError(e.to_string()))?;
+
+    if decompressed.len() != uncompressed_len {
+        return Err(CryptoError::DecryptionFailed);
+    }
+
+    Ok(decompressed)
+}
+
+/// Deriva uma chave de sessão final combinando uma chave efêmera já derivada
+/// com um segredo estático adicional, via HKDF-SHA256. Usada por
+/// `derive_authenticated_session_keys` para a direção rx e tx separadamente.
+fn derive_authenticated_key(ephemeral_key: &SessionKey, static_secret: &[u8], info: &[u8]) -> SessionKey {
+    let hk = Hkdf::<Sha256>::new(Some(static_secret), ephemeral_key.as_ref());
+    let mut bytes = [0u8; secretbox::KEYBYTES];
+    hk.expand(info, &mut bytes)
+        .expect("HKDF output length is always valid for secretbox::KEYBYTES");
+    SessionKey::from_slice(&bytes).expect("derived key has correct length")
+}
+
+/// XORa o contador big-endian de 64 bits do registro nos últimos bytes do
+/// nonce base, produzindo um nonce por registro único e determinístico.
+fn record_nonce(base: &[u8; secretbox::NONCEBYTES], counter: u64) -> SecretBoxNonce {
+    let mut nonce_bytes = *base;
+    let counter_bytes = counter.to_be_bytes();
+    let offset = nonce_bytes.len() - counter_bytes.len();
+    for (i, b) in counter_bytes.iter().enumerate() {
+        nonce_bytes[offset + i] ^= b;
+    }
+    SecretBoxNonce::from_slice(&nonce_bytes).expect("nonce has correct length")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,7 +937,7 @@ mod tests {
         Crypto::init();
         let id = Crypto::generate_identity();
         assert!(!id.public_key.is_empty());
-        assert!(!id.secret_key.is_empty());
+        assert!(!id.secret_key.expose().is_empty());
     }
 
     #[test]
@@ -267,24 +947,102 @@ mod tests {
         let bob = Crypto::generate_identity();
 
         let alice_pk = PublicKey::from_slice(&BASE64.decode(&alice.public_key).unwrap()).unwrap();
-        let alice_sk = SecretKey::from_slice(&BASE64.decode(&alice.secret_key).unwrap()).unwrap();
+        let alice_sk = SecretKey::from_slice(&BASE64.decode(alice.secret_key.expose()).unwrap()).unwrap();
         let bob_pk = PublicKey::from_slice(&BASE64.decode(&bob.public_key).unwrap()).unwrap();
-        let bob_sk = SecretKey::from_slice(&BASE64.decode(&bob.secret_key).unwrap()).unwrap();
+        let bob_sk = SecretKey::from_slice(&BASE64.decode(bob.secret_key.expose()).unwrap()).unwrap();
 
         let alice_session = Crypto::client_session_keys(&alice_pk, &alice_sk, &bob_pk).unwrap();
         let bob_session = Crypto::server_session_keys(&bob_pk, &bob_sk, &alice_pk).unwrap();
 
         let msg = b"Hello Bob!";
-        let encrypted = Crypto::encrypt(msg, &alice_session.tx);
-        let decrypted = Crypto::decrypt(&encrypted, &bob_session.rx).unwrap();
+        let encrypted = Crypto::encrypt(msg, alice_session.tx.expose());
+        let decrypted = Crypto::decrypt(&encrypted, bob_session.rx.expose()).unwrap();
         assert_eq!(msg, decrypted.as_slice());
 
         let reply = b"Hi Alice!";
-        let encrypted_reply = Crypto::encrypt(reply, &bob_session.tx);
-        let decrypted_reply = Crypto::decrypt(&encrypted_reply, &alice_session.rx).unwrap();
+        let encrypted_reply = Crypto::encrypt(reply, bob_session.tx.expose());
+        let decrypted_reply = Crypto::decrypt(&encrypted_reply, alice_session.rx.expose()).unwrap();
         assert_eq!(reply, decrypted_reply.as_slice());
     }
 
+    #[test]
+    fn test_static_shared_secret_is_symmetric() {
+        Crypto::init();
+        let alice = Crypto::generate_identity();
+        let bob = Crypto::generate_identity();
+
+        let alice_pk = PublicKey::from_slice(&BASE64.decode(&alice.public_key).unwrap()).unwrap();
+        let bob_pk = PublicKey::from_slice(&BASE64.decode(&bob.public_key).unwrap()).unwrap();
+
+        let from_alice = Crypto::static_shared_secret(&alice, &bob_pk).unwrap();
+        let from_bob = Crypto::static_shared_secret(&bob, &alice_pk).unwrap();
+        assert_eq!(from_alice, from_bob);
+    }
+
+    #[test]
+    fn test_derive_authenticated_session_keys_matches_across_sides() {
+        Crypto::init();
+        let alice = Crypto::generate_identity();
+        let bob = Crypto::generate_identity();
+        let alice_identity_pk = PublicKey::from_slice(&BASE64.decode(&alice.public_key).unwrap()).unwrap();
+        let bob_identity_pk = PublicKey::from_slice(&BASE64.decode(&bob.public_key).unwrap()).unwrap();
+
+        // Reaproveita generate_identity só para obter um par X25519 efêmero fresco.
+        let alice_ephemeral = Crypto::generate_identity();
+        let bob_ephemeral = Crypto::generate_identity();
+        let alice_eph_pk = PublicKey::from_slice(&BASE64.decode(&alice_ephemeral.public_key).unwrap()).unwrap();
+        let alice_eph_sk = SecretKey::from_slice(&BASE64.decode(alice_ephemeral.secret_key.expose()).unwrap()).unwrap();
+        let bob_eph_pk = PublicKey::from_slice(&BASE64.decode(&bob_ephemeral.public_key).unwrap()).unwrap();
+        let bob_eph_sk = SecretKey::from_slice(&BASE64.decode(bob_ephemeral.secret_key.expose()).unwrap()).unwrap();
+
+        let alice_session = Crypto::client_session_keys(&alice_eph_pk, &alice_eph_sk, &bob_eph_pk).unwrap();
+        let bob_session = Crypto::server_session_keys(&bob_eph_pk, &bob_eph_sk, &alice_eph_pk).unwrap();
+
+        let static_secret_alice = Crypto::static_shared_secret(&alice, &bob_identity_pk).unwrap();
+        let static_secret_bob = Crypto::static_shared_secret(&bob, &alice_identity_pk).unwrap();
+
+        let client_nonce = Crypto::random_bytes(16);
+        let server_nonce = Crypto::random_bytes(16);
+
+        let alice_final = Crypto::derive_authenticated_session_keys(
+            &alice_session, &static_secret_alice, &client_nonce, &server_nonce,
+        );
+        let bob_final = Crypto::derive_authenticated_session_keys(
+            &bob_session, &static_secret_bob, &client_nonce, &server_nonce,
+        );
+
+        let msg = b"ola autenticado";
+        let encrypted = Crypto::encrypt(msg, alice_final.tx.expose());
+        let decrypted = Crypto::decrypt(&encrypted, bob_final.rx.expose()).unwrap();
+        assert_eq!(msg, decrypted.as_slice());
+
+        let other_nonce = Crypto::random_bytes(16);
+        let alice_with_other_nonce = Crypto::derive_authenticated_session_keys(
+            &alice_session, &static_secret_alice, &other_nonce, &server_nonce,
+        );
+        assert_ne!(alice_final.tx.expose().as_ref(), alice_with_other_nonce.tx.expose().as_ref());
+    }
+
+    #[test]
+    fn test_derive_contacts_key_is_deterministic_and_identity_specific() {
+        Crypto::init();
+        let alice = Crypto::generate_identity();
+        let bob = Crypto::generate_identity();
+
+        let alice_key_again = Crypto::derive_contacts_key(&alice).unwrap();
+        let alice_key = Crypto::derive_contacts_key(&alice).unwrap();
+        assert_eq!(alice_key.as_ref(), alice_key_again.as_ref());
+
+        let bob_key = Crypto::derive_contacts_key(&bob).unwrap();
+        assert_ne!(alice_key.as_ref(), bob_key.as_ref());
+
+        let msg = b"contato secreto";
+        let encrypted = Crypto::encrypt(msg, &alice_key);
+        let decrypted = Crypto::decrypt(&encrypted, &alice_key).unwrap();
+        assert_eq!(msg, decrypted.as_slice());
+        assert!(Crypto::decrypt(&encrypted, &bob_key).is_err());
+    }
+
     #[test]
     fn test_fingerprint() {
         Crypto::init();
@@ -299,12 +1057,164 @@ mod tests {
     fn test_password_encryption() {
         Crypto::init();
         let data = b"secrete data";
-        let password = "strong-password";
+        let password = SafePassword::new("strong-password".to_string());
 
-        let encrypted = Crypto::encrypt_with_password(data, password).unwrap();
-        let decrypted = Crypto::decrypt_with_password(&encrypted, password).unwrap();
+        let encrypted = Crypto::encrypt_with_password(data, &password).unwrap();
+        let decrypted = Crypto::decrypt_with_password(&encrypted, &password).unwrap();
         assert_eq!(data, decrypted.as_slice());
 
-        assert!(Crypto::decrypt_with_password(&encrypted, "wrong").is_err());
+        let wrong = SafePassword::new("wrong".to_string());
+        assert!(Crypto::decrypt_with_password(&encrypted, &wrong).is_err());
+    }
+
+    #[test]
+    fn test_seal_identity_roundtrip_and_wrong_password() {
+        Crypto::init();
+        let data = b"identity key material";
+        let password = SafePassword::new("strong-password".to_string());
+
+        let (params, sealed) = Crypto::seal_identity(data, &password).unwrap();
+        assert_eq!(params.kdf_id, KDF_ID_ARGON2ID);
+        assert!(params.meets_current_cost());
+
+        let opened = Crypto::open_identity(&params, &sealed, &password).unwrap();
+        assert_eq!(data, opened.as_slice());
+
+        let wrong = SafePassword::new("wrong".to_string());
+        assert!(Crypto::open_identity(&params, &sealed, &wrong).is_err());
+    }
+
+    #[test]
+    fn test_seal_identity_still_opens_with_downgraded_cost_parameters() {
+        Crypto::init();
+        let data = b"old identity";
+        let password = SafePassword::new("legacy-password".to_string());
+
+        // Simula um envelope selado por uma versão anterior da crate, com
+        // um custo menor que o atualmente recomendado: a chave é derivada
+        // já com esses parâmetros e os dados são selados com ela.
+        let salt_bytes: [u8; 16] = randombytes(16).try_into().unwrap();
+        let mut params = KdfParams::current(salt_bytes);
+        params.ops_limit = 1;
+        assert!(!params.meets_current_cost());
+
+        let key = params.derive_key(&password).unwrap();
+        let key_box = secretbox::Key::from_slice(key.expose()).unwrap();
+        let nonce = secretbox::gen_nonce();
+        let mut sealed = nonce.as_ref().to_vec();
+        sealed.extend_from_slice(&secretbox::seal(data, &nonce, &key_box));
+
+        // Os parâmetros antigos gravados no envelope continuam sendo os
+        // usados para derivar a chave, então o arquivo ainda abre.
+        let opened = Crypto::open_identity(&params, &sealed, &password).unwrap();
+        assert_eq!(data, opened.as_slice());
+    }
+
+    #[test]
+    fn test_open_identity_rejects_unknown_kdf_id() {
+        Crypto::init();
+        let data = b"identity";
+        let password = SafePassword::new("pw".to_string());
+        let (mut params, sealed) = Crypto::seal_identity(data, &password).unwrap();
+        params.kdf_id = "scrypt".to_string();
+
+        match Crypto::open_identity(&params, &sealed, &password) {
+            Err(CryptoError::UnsupportedKdf(id)) => assert_eq!(id, "scrypt"),
+            other => panic!("expected UnsupportedKdf, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_mnemonic_roundtrip() {
+        Crypto::init();
+        let keypair = Crypto::generate_identity();
+
+        let phrase = Crypto::identity_to_mnemonic(&keypair).unwrap();
+        assert_eq!(phrase.len(), 24);
+
+        let restored = Crypto::identity_from_mnemonic(&phrase).unwrap();
+        assert_eq!(restored.public_key, keypair.public_key);
+        assert_eq!(restored.secret_key.expose(), keypair.secret_key.expose());
+    }
+
+    #[test]
+    fn test_mnemonic_rejects_bad_checksum() {
+        Crypto::init();
+        let keypair = Crypto::generate_identity();
+        let mut phrase = Crypto::identity_to_mnemonic(&keypair).unwrap();
+
+        // Troca a última palavra (que carrega o checksum) por outra qualquer.
+        let words = wordlist::words();
+        let replacement = if phrase[23] == words[0] { &words[1] } else { &words[0] };
+        phrase[23] = replacement.clone();
+
+        assert!(Crypto::identity_from_mnemonic(&phrase).is_err());
+    }
+
+    #[test]
+    fn test_stream_encrypt_decrypt_roundtrip() {
+        Crypto::init();
+        let key = secretbox::gen_key();
+        let session_key = SessionKey::from_slice(key.as_ref()).unwrap();
+
+        let plaintext = b"A".repeat(10_000);
+        let (header, records) = Crypto::encrypt_stream(&plaintext, &session_key, 1024);
+        assert!(records.len() > 1);
+
+        let decrypted = Crypto::decrypt_stream(&header, &records, &session_key).unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_stream_decrypt_detects_truncation() {
+        Crypto::init();
+        let key = secretbox::gen_key();
+        let session_key = SessionKey::from_slice(key.as_ref()).unwrap();
+
+        let plaintext = b"A".repeat(5_000);
+        let (header, mut records) = Crypto::encrypt_stream(&plaintext, &session_key, 1024);
+        records.pop(); // descarta o registro final (que carrega o delimitador 0x02)
+
+        assert!(Crypto::decrypt_stream(&header, &records, &session_key).is_err());
+    }
+
+    #[test]
+    fn test_compressed_encrypt_decrypt_roundtrip() {
+        Crypto::init();
+        let key = secretbox::gen_key();
+        let session_key = SessionKey::from_slice(key.as_ref()).unwrap();
+
+        let message = b"a".repeat(50_000); // altamente compressível
+        let encrypted = Crypto::encrypt_compressed(&message, &session_key).unwrap();
+        assert!(BASE64.decode(&encrypted.ciphertext).unwrap().len() < message.len());
+
+        let decrypted = Crypto::decrypt_compressed(&encrypted, &session_key, message.len() + 1).unwrap();
+        assert_eq!(decrypted, message);
+    }
+
+    #[test]
+    fn test_compressed_rejects_oversized_declared_length() {
+        Crypto::init();
+        let key = secretbox::gen_key();
+        let session_key = SessionKey::from_slice(key.as_ref()).unwrap();
+
+        let message = b"a".repeat(50_000);
+        let encrypted = Crypto::encrypt_compressed(&message, &session_key).unwrap();
+
+        assert!(matches!(
+            Crypto::decrypt_compressed(&encrypted, &session_key, 1024),
+            Err(CryptoError::DecompressionBombRejected)
+        ));
+    }
+
+    #[test]
+    fn test_password_compressed_roundtrip() {
+        Crypto::init();
+        let data = b"repeat ".repeat(5_000);
+        let password = SafePassword::new("strong-password".to_string());
+
+        let encrypted = Crypto::encrypt_with_password_compressed(&data, &password).unwrap();
+        let decrypted = Crypto::decrypt_with_password_compressed(&encrypted, &password, data.len() + 1).unwrap();
+        assert_eq!(decrypted, data);
     }
 }
@@ -0,0 +1,59 @@
+//! Lista fixa de 2048 palavras usada pelo backup/restauração por frase mnemônica.
+//!
+//! Em vez de embutir a wordlist BIP39 inglesa padrão, geramos uma lista própria
+//! e determinística (prefixo + núcleo + sufixo, 8 * 8 * 32 = 2048 combinações),
+//! pronunciável e fácil de transcrever à mão. O importante é que a lista seja
+//! fixa e que a mesma posição sempre mapeie para a mesma palavra.
+
+use std::sync::OnceLock;
+
+const PREFIXES: [&str; 8] = ["ab", "bri", "cal", "dor", "el", "fan", "gor", "hu"];
+const CORES: [&str; 8] = ["ta", "ne", "ri", "lo", "mu", "sa", "ve", "zo"];
+const SUFFIXES: [&str; 32] = [
+    "ba", "ca", "da", "fa", "ga", "ja", "ka", "la", "ma", "na", "pa", "ra", "sa", "ta", "va", "za",
+    "bo", "co", "do", "fo", "go", "jo", "ko", "lo", "mo", "no", "po", "ro", "so", "to", "vo", "zo",
+];
+
+static WORDS: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Retorna a wordlist fixa de 2048 entradas (índice == posição BIP39-style de 11 bits).
+pub fn words() -> &'static [String] {
+    WORDS.get_or_init(|| {
+        let mut list = Vec::with_capacity(PREFIXES.len() * CORES.len() * SUFFIXES.len());
+        for prefix in PREFIXES {
+            for core in CORES {
+                for suffix in SUFFIXES {
+                    list.push(format!("{prefix}{core}{suffix}"));
+                }
+            }
+        }
+        list
+    })
+}
+
+/// Encontra o índice (0..2048) de uma palavra na wordlist fixa.
+pub fn index_of(word: &str) -> Option<u16> {
+    words().iter().position(|w| w == word).map(|i| i as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wordlist_has_2048_unique_entries() {
+        let list = words();
+        assert_eq!(list.len(), 2048);
+        let mut sorted = list.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 2048);
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let list = words();
+        assert_eq!(index_of(&list[42]), Some(42));
+        assert_eq!(index_of("not-a-real-word"), None);
+    }
+}
@@ -6,6 +6,8 @@
 pub mod tor_manager;
 pub mod crypto;
 pub mod storage;
+pub mod oplog;
+pub mod mux;
 // Estes módulos são opcionais dependendo se você já criou os arquivos.
 // Se ainda não criou 'protocol.rs' ou 'config.rs', comente as linhas abaixo.
 pub mod protocol;
@@ -127,6 +129,6 @@ mod tests {
         
         // Testa se conseguimos adquirir o lock de leitura do TorManager
         let manager = state.tor_manager.read().await;
-        assert_eq!(*manager.get_status(), TorStatus::NotStarted);
+        assert_eq!(manager.get_status(), TorStatus::NotStarted);
     }
 }
@@ -1,12 +1,9 @@
 use std::io::{self, Write};
 use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
 use tokio::time::sleep;
-use rand::Rng;
-use futures::stream::StreamExt;
-use hyper::service::service_fn;
-use hyper::{Body, Request, Response, StatusCode};
-use hyper::server::conn::Http;
-use torchat_paste_core::{AppState, Contact, TorManager, crypto::Fingerprint};
+use torchat_paste_core::{AppState, Contact, TorManager, crypto, crypto::Fingerprint, protocol, tor_manager};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -15,9 +12,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let state = AppState::new();
 
-    gerenciar_identidade(&state).await?;
+    let identity = gerenciar_identidade(&state).await?;
     inicializar_tor(&state).await?;
-    criar_servico_oculto(&state).await?;
+    criar_servico_oculto(&state, identity).await?;
 
     loop {
         println!("\n--- Menu Principal ---");
@@ -25,7 +22,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("2. Adicionar contato");
         println!("3. Conversar com contato");
         println!("4. Compartilhar endereço (OnionShare)");
-        println!("5. Sair");
+        println!("5. Baixar arquivo compartilhado (OnionShare)");
+        println!("6. Sair");
         print!("Escolha: ");
         io::stdout().flush()?;
 
@@ -36,7 +34,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "2" => adicionar_contato(&state).await?,
             "3" => conversar(&state).await?,
             "4" => compartilhar_endereco(&state).await?,
-            "5" => {
+            "5" => baixar_arquivo_compartilhado(&state).await?,
+            "6" => {
                 println!("Encerrando...");
                 break;
             }
@@ -47,21 +46,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn gerenciar_identidade(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+async fn gerenciar_identidade(state: &AppState) -> Result<crypto::IdentityKeyPair, Box<dyn std::error::Error>> {
     let storage = state.storage.as_ref();
 
     if storage.has_identity() {
         println!("Identidade existente encontrada. Digite sua senha:");
         print!("Senha: ");
         io::stdout().flush()?;
-        let mut password = String::new();
-        io::stdin().read_line(&mut password)?;
-        let password = password.trim();
+        let password = crypto::SafePassword::from_stdin()?;
 
-        match storage.load_identity(password) {
+        match storage.load_identity(&password) {
             Ok(keypair) => {
                 println!("Identidade carregada com sucesso!");
                 println!("Chave pública: {}", keypair.public_key);
+                Ok(keypair)
             }
             Err(e) => {
                 eprintln!("Falha ao carregar identidade: {}", e);
@@ -72,15 +70,14 @@ async fn gerenciar_identidade(state: &AppState) -> Result<(), Box<dyn std::error
         println!("Nenhuma identidade encontrada. Vamos criar uma nova.");
         print!("Defina uma senha para proteger sua identidade: ");
         io::stdout().flush()?;
-        let mut password = String::new();
-        io::stdin().read_line(&mut password)?;
-        let password = password.trim();
+        let password = crypto::SafePassword::from_stdin()?;
 
-        match storage.create_identity(password) {
+        match storage.create_identity(&password) {
             Ok(fingerprint) => {
                 println!("Identidade criada com sucesso!");
                 println!("Fingerprint da sua chave pública: {}", fingerprint.formatted());
                 println!("Guarde este fingerprint e compartilhe com seus contatos para verificação.");
+                Ok(storage.load_identity(&password)?)
             }
             Err(e) => {
                 eprintln!("Falha ao criar identidade: {}", e);
@@ -88,7 +85,6 @@ async fn gerenciar_identidade(state: &AppState) -> Result<(), Box<dyn std::error
             }
         }
     }
-    Ok(())
 }
 
 async fn inicializar_tor(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
@@ -106,25 +102,48 @@ async fn inicializar_tor(state: &AppState) -> Result<(), Box<dyn std::error::Err
     }
 }
 
-async fn criar_servico_oculto(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
-    let mut tor_manager = state.tor_manager.write().await;
-    if tor_manager.get_onion_address().is_none() {
-        println!("Criando serviço oculto...");
-        match tor_manager.create_hidden_service().await {
-            Ok(onion) => {
-                println!("Seu endereço onion: {}", onion);
-            }
-            Err(e) => {
-                eprintln!("Falha ao criar serviço oculto: {}", e);
-                std::process::exit(1);
+async fn criar_servico_oculto(
+    state: &AppState,
+    identity: crypto::IdentityKeyPair,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Registra identidade e handler de mensagens antes de criar o serviço
+    // oculto: o loop de aceitação de conexões captura os dois no momento em
+    // que é criado, então registrar depois deixaria conexões já aceitas sem
+    // como completar o handshake autenticado ou entregar mensagens.
+    let (incoming_tx, mut incoming_rx) = mpsc::channel(32);
+    {
+        let mut tor_manager = state.tor_manager.write().await;
+        tor_manager.set_local_identity(identity);
+        tor_manager.set_incoming_handler(incoming_tx);
+
+        if tor_manager.get_onion_address().is_none() {
+            println!("Criando serviço oculto...");
+            match tor_manager.create_hidden_service().await {
+                Ok(onion) => {
+                    println!("Seu endereço onion: {}", onion);
+                }
+                Err(e) => {
+                    eprintln!("Falha ao criar serviço oculto: {}", e);
+                    std::process::exit(1);
+                }
             }
+        } else {
+            println!(
+                "Serviço oculto já ativo: {}",
+                tor_manager.get_onion_address().unwrap()
+            );
         }
-    } else {
-        println!(
-            "Serviço oculto já ativo: {}",
-            tor_manager.get_onion_address().unwrap()
-        );
     }
+
+    tokio::spawn(async move {
+        while let Some((connection, message)) = incoming_rx.recv().await {
+            println!(
+                "\n[mensagem recebida de {}]: {}",
+                connection.remote_address, message.content
+            );
+        }
+    });
+
     Ok(())
 }
 
@@ -196,6 +215,64 @@ async fn adicionar_contato(state: &AppState) -> Result<(), Box<dyn std::error::E
     Ok(())
 }
 
+/// Política de confiança usada pelo caminho de conexão de `conversar`, no
+/// mesmo espírito de um `HttpClientOptions`: decide como tratar o fingerprint
+/// de identidade que o par apresenta durante o handshake.
+struct ConnectOptions {
+    /// Fingerprint já conhecido para este endereço (vindo de
+    /// `SecureStorage::find_contact`), se houver. `None` significa que o par
+    /// ainda não foi visto antes.
+    fingerprint: Option<Fingerprint>,
+    /// Se `true`, um fingerprint já confirmado é reaproveitado em conexões
+    /// futuras sem pedir confirmação de novo (trust-on-first-use). Se
+    /// `false`, toda conexão exige confirmação explícita do usuário, mesmo
+    /// para um par já conhecido.
+    fingerprint_cache: bool,
+    /// Se `true`, ao encontrar um par cujo fingerprint ainda não está
+    /// confiado, pede confirmação interativa ao usuário antes de prosseguir.
+    /// Se `false`, pares não confiados são sempre recusados.
+    interactive: bool,
+}
+
+/// Decide se o fingerprint apresentado pelo par após o handshake deve ser
+/// aceito, aplicando a política de `ConnectOptions`. Se o par já era
+/// conhecido e `fingerprint_cache` está ativo, o handshake já validou o
+/// fingerprint contra o valor salvo (via `expected_fingerprint`) e esta
+/// função apenas confirma a aceitação. Caso contrário — par novo, ou cache
+/// desativado — pede confirmação interativa e, se aceita com o cache ativo,
+/// grava o fingerprint com `add_contact` (trust-on-first-use).
+fn autenticar_par(
+    state: &AppState,
+    contact: &Contact,
+    options: &ConnectOptions,
+    peer_fingerprint: &Fingerprint,
+) -> io::Result<bool> {
+    if options.fingerprint_cache && options.fingerprint.is_some() {
+        return Ok(true);
+    }
+
+    if !options.interactive {
+        return Ok(false);
+    }
+
+    println!("Fingerprint apresentado por {}: {}", contact.address, peer_fingerprint.formatted());
+    print!("Confiar neste fingerprint e lembrá-lo para conexões futuras? (s/N): ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    if !answer.trim().eq_ignore_ascii_case("s") {
+        return Ok(false);
+    }
+
+    if options.fingerprint_cache {
+        if let Err(e) = state.storage.add_contact(&contact.address, &contact.nickname, peer_fingerprint.clone()) {
+            eprintln!("Não foi possível salvar o fingerprint: {}", e);
+        }
+    }
+    Ok(true)
+}
+
 async fn conversar(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
     println!("Iniciar conversa:");
     listar_contatos(state).await?;
@@ -216,11 +293,72 @@ async fn conversar(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
 
         let tor_manager = state.tor_manager.read().await;
         match tor_manager.connect_to_onion(&contact.address, 8080).await {
-            Ok(stream) => {
-                println!("Conexão TCP estabelecida!");
-                // Aqui viria a lógica de handshake e troca de mensagens
-                drop(stream);
-                println!("(Handshake ainda não implementado)");
+            Ok(mut stream) => {
+                println!("Conexão TCP estabelecida! Realizando handshake autenticado...");
+
+                print!("Senha da sua identidade: ");
+                io::stdout().flush()?;
+                let password = crypto::SafePassword::from_stdin()?;
+                let identity = match state.storage.load_identity(&password) {
+                    Ok(identity) => identity,
+                    Err(e) => {
+                        eprintln!("Não foi possível carregar sua identidade: {}", e);
+                        return Ok(());
+                    }
+                };
+
+                let options = ConnectOptions {
+                    fingerprint: state.storage.find_contact(&contact.address).map(|stored| stored.fingerprint),
+                    fingerprint_cache: true,
+                    interactive: true,
+                };
+
+                // Quando o cache de fingerprints está desligado, não deixamos o
+                // próprio handshake confiar cegamente em um valor salvo: a
+                // confirmação interativa em `autenticar_par` decide sozinha.
+                let handshake_expected = if options.fingerprint_cache {
+                    options.fingerprint.as_ref()
+                } else {
+                    None
+                };
+
+                let handshake_protocol = protocol::ChatProtocol::new();
+                match protocol::run_client_handshake(
+                    &mut stream,
+                    &handshake_protocol,
+                    &identity,
+                    handshake_expected,
+                ).await {
+                    Ok(outcome) => {
+                        let peer_fingerprint = crypto::Fingerprint::from_public_key(&outcome.peer_identity_key);
+                        match autenticar_par(state, &contact, &options, &peer_fingerprint) {
+                            Ok(true) => {
+                                println!("Canal seguro estabelecido com {}.", contact.address);
+                                // A troca de mensagens usando `outcome.session_keys` fica
+                                // para uma próxima etapa do menu de conversa.
+                            }
+                            Ok(false) => {
+                                eprintln!(
+                                    "Conexão recusada: fingerprint de {} não foi confirmado.",
+                                    contact.address
+                                );
+                            }
+                            Err(e) => {
+                                eprintln!("Falha ao confirmar fingerprint: {}", e);
+                            }
+                        }
+                    }
+                    Err(protocol::ProtocolError::FingerprintMismatch) => {
+                        eprintln!(
+                            "ALERTA: o fingerprint apresentado por {} não corresponde ao fingerprint salvo. \
+                             Conexão abortada para evitar falar com um impostor.",
+                            contact.address
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("Falha no handshake: {}", e);
+                    }
+                }
             }
             Err(e) => {
                 eprintln!("Falha na conexão: {}", e);
@@ -233,7 +371,9 @@ async fn conversar(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-/// Compartilha o endereço onion principal criando um serviço efêmero (estilo OnionShare)
+/// Compartilha o endereço onion principal criando um serviço efêmero (estilo
+/// OnionShare), opcionalmente anexando um arquivo cujo digest SHA3-256 é
+/// anunciado em um manifesto selado (ver `TorManager::create_ephemeral_sharing_service`).
 async fn compartilhar_endereco(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
     let meu_onion = {
         let tor_manager = state.tor_manager.read().await;
@@ -243,54 +383,48 @@ async fn compartilhar_endereco(state: &AppState) -> Result<(), Box<dyn std::erro
     match meu_onion {
         Some(onion) => {
             println!("Seu endereço onion principal: {}", onion);
-            println!("Criando link de compartilhamento temporário via OnionShare...");
 
-            // Gera uma porta local aleatória
-            let mut rng = rand::thread_rng();
-            let local_port = rng.gen_range(10000..20000);
+            print!("Caminho de um arquivo para anexar (Enter para compartilhar só o endereço): ");
+            io::stdout().flush()?;
+            let mut file_path = String::new();
+            io::stdin().read_line(&mut file_path)?;
+            let file_path = file_path.trim();
+
+            let file = if file_path.is_empty() {
+                None
+            } else {
+                match tor_manager::FileShare::from_path(std::path::Path::new(file_path)).await {
+                    Ok(f) => Some(f),
+                    Err(e) => {
+                        eprintln!("Não foi possível ler '{}': {}", file_path, e);
+                        return Ok(());
+                    }
+                }
+            };
+
+            println!("Criando link de compartilhamento temporário via OnionShare...");
 
-            // Cria um serviço onion efêmero apontando para essa porta
-            let (onion_ephemeral, mut requests) = {
+            // Cria o serviço onion efêmero. Ele já processa as conexões
+            // aceitas internamente: cada stream onion é multiplexada com
+            // `MuxConnection` e o HTTP roda sobre a substream "file", em vez
+            // de sobre a conexão inteira (ver
+            // `TorManager::create_ephemeral_sharing_service`).
+            let (onion_ephemeral, manifest_key, _running) = {
                 let tor_manager = state.tor_manager.write().await;
-                tor_manager.create_ephemeral_sharing_service(&onion).await?
+                tor_manager.create_ephemeral_sharing_service(&onion, file).await?
             };
 
             let link = format!("http://{}/", onion_ephemeral);
             println!("Link temporário (válido por 5 minutos): {}", link);
-            println!("Compartilhe este link com seu contato por um canal seguro.");
-
-            // Função auxiliar para servir HTTP em uma stream
-            async fn serve_http(
-                stream: impl tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
-                meu_onion: String,
-            ) -> Result<(), hyper::Error> {
-                let service = service_fn(move |_req: Request<Body>| {
-                    let meu_onion = meu_onion.clone();
-                    async move {
-                        Ok::<_, hyper::Error>(Response::new(Body::from(meu_onion)))
-                    }
-                });
-                Http::new().serve_connection(stream, service).await
+            if let Some(key) = &manifest_key {
+                // A chave do manifesto não viaja pelo serviço onion: ela é
+                // mostrada separadamente para que o remetente a entregue só
+                // por um canal que o próprio link não precisa passar,
+                // deixando claro que ela nunca deve ser postada junto do link
+                // em um canal que alguém mais possa adulterar.
+                println!("Chave do manifesto (envie por um canal separado do link): {}", key);
             }
-
-            // Processa as requisições do serviço onion em background
-            let handle = tokio::spawn(async move {
-                while let Some(request) = requests.next().await {
-                    match request.accept().await {
-                        Ok(stream) => {
-                            let onion_clone = onion.clone();
-                            tokio::spawn(async move {
-                                if let Err(e) = serve_http(stream, onion_clone).await {
-                                    eprintln!("Erro ao servir HTTP: {}", e);
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            eprintln!("Erro ao aceitar requisição onion: {}", e);
-                        }
-                    }
-                }
-            });
+            println!("Compartilhe este link com seu contato por um canal seguro.");
 
             println!("Aguardando compartilhamento por até 5 minutos...");
             println!("Pressione Enter para cancelar manualmente.");
@@ -301,16 +435,16 @@ async fn compartilhar_endereco(state: &AppState) -> Result<(), Box<dyn std::erro
                     println!("Tempo esgotado. Link expirado.");
                 }
                 _ = async {
-                    let mut input = String::new();
-                    io::stdin().read_line(&mut input).await.ok();
+                    let mut buf = String::new();
+                    let mut stdin = BufReader::new(tokio::io::stdin());
+                    let _ = stdin.read_line(&mut buf).await;
                 } => {
                     println!("Compartilhamento cancelado.");
                 }
             }
 
-            // Encerra a task de processamento
-            handle.abort();
-            // O serviço onion efêmero será dropado ao sair do escopo
+            // O serviço onion efêmero (e a task que o serve) são encerrados
+            // ao sair do escopo, quando `_running` é dropado.
             println!("Link de compartilhamento desativado.");
             Ok(())
         }
@@ -320,3 +454,41 @@ async fn compartilhar_endereco(state: &AppState) -> Result<(), Box<dyn std::erro
         }
     }
 }
+
+/// Baixa um arquivo anunciado por `compartilhar_endereco` em outro nó,
+/// validando-o contra o manifesto selado enquanto o grava em disco e
+/// removendo-o se o digest não bater (ver `TorManager::download_shared_file`).
+async fn baixar_arquivo_compartilhado(state: &AppState) -> Result<(), Box<dyn std::error::Error>> {
+    print!("Endereço onion efêmero (XXXX.onion): ");
+    io::stdout().flush()?;
+    let mut onion = String::new();
+    io::stdin().read_line(&mut onion)?;
+    let onion = onion.trim();
+
+    print!("Chave do manifesto: ");
+    io::stdout().flush()?;
+    let mut manifest_key = String::new();
+    io::stdin().read_line(&mut manifest_key)?;
+    let manifest_key = manifest_key.trim();
+
+    print!("Salvar como: ");
+    io::stdout().flush()?;
+    let mut dest = String::new();
+    io::stdin().read_line(&mut dest)?;
+    let dest = dest.trim();
+
+    let tor_manager = state.tor_manager.read().await;
+    match tor_manager.download_shared_file(onion, manifest_key, std::path::Path::new(dest)).await {
+        Ok(manifest) => {
+            println!(
+                "Arquivo '{}' ({} bytes) baixado e verificado com sucesso em '{}'.",
+                manifest.file_name, manifest.size, dest
+            );
+        }
+        Err(e) => {
+            eprintln!("Falha ao baixar arquivo compartilhado: {}", e);
+        }
+    }
+
+    Ok(())
+}
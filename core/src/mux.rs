@@ -0,0 +1,335 @@
+//! Transporte multiplexado: uma única conexão onion (já aceita ou já
+//! conectada) passa a carregar várias "substreams" lógicas independentes —
+//! chat, compartilhamento de arquivo, presença/heartbeat — em vez de cada
+//! funcionalidade precisar abrir seu próprio serviço onion ou sua própria
+//! conexão SOCKS5. O desenho segue o espírito de um muxer estilo libp2p
+//! (torut/tokio-socks por baixo, um muxer como mplex/yamux por cima), mas,
+//! seguindo o mesmo caminho que o resto deste repositório já escolheu para
+//! `protocol.rs` (framing e handshake escritos à mão em vez de uma
+//! dependência externa), o muxer aqui também é uma implementação própria e
+//! simples sobre o mesmo framing length-delimited usado no resto do código,
+//! em vez de depender de um crate `yamux`/`mplex`.
+//!
+//! Cada substream é identificada por um `u32` escolhido por quem a abre e
+//! rotulada com um nome lógico (ver [`SUBSTREAM_CHAT`], [`SUBSTREAM_FILE`],
+//! [`SUBSTREAM_PRESENCE`]) usado só para quem recebe saber para qual handler
+//! encaminhá-la. Qualquer um dos lados pode abrir uma substream a qualquer
+//! momento; quem recebe usa [`MuxConnection::accept_substream`] para pegá-las
+//! conforme chegam. Cada substream aceita/aberta vira um `tokio::io::duplex`
+//! comum, então pode ser entregue diretamente a qualquer código que já
+//! espere uma stream (ex.: `Http::serve_connection`).
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{self, split, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::sync::{mpsc, Mutex};
+
+/// Nome lógico da substream de controle/chat.
+pub const SUBSTREAM_CHAT: &str = "chat";
+/// Nome lógico da substream de compartilhamento de arquivo.
+pub const SUBSTREAM_FILE: &str = "file";
+/// Nome lógico da substream de presença/heartbeat.
+pub const SUBSTREAM_PRESENCE: &str = "presence";
+
+/// Tamanho do buffer interno de cada substream (mesma ordem de grandeza já
+/// usada pelos pares `tokio::io::duplex` nos testes deste repositório).
+const SUBSTREAM_BUFFER: usize = 8192;
+
+/// Tamanho máximo aceito para o payload de um frame multiplexado, pelo mesmo
+/// motivo de `protocol::MAX_FRAME_SIZE`: não deixar um par malicioso forçar
+/// a alocação de um buffer arbitrariamente grande.
+const MAX_MUX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum MuxError {
+    #[error("Erro de I/O na conexão multiplexada: {0}")]
+    Io(String),
+    #[error("A conexão multiplexada foi encerrada")]
+    Closed,
+}
+
+/// Lado do aplicativo de uma substream: um `tokio::io::duplex` já conectado
+/// ao resto da `MuxConnection` por uma task interna de bombeamento.
+pub type MuxStream = DuplexStream;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum MuxFrame {
+    /// Anuncia uma nova substream ao par, identificada por `substream_id` e
+    /// rotulada com `name`.
+    Open { substream_id: u32, name: String },
+    /// Um pedaço de dados (base64) de uma substream já aberta.
+    Data { substream_id: u32, payload: String },
+    /// Encerra uma substream; o lado que recebe trata isso como EOF.
+    Close { substream_id: u32 },
+}
+
+async fn read_mux_frame<S>(stream: &mut S) -> io::Result<Option<MuxFrame>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut len_buf = [0u8; 4];
+    match stream.read_exact(&mut len_buf).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > MAX_MUX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame multiplexado excede o tamanho máximo aceito"));
+    }
+
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).await?;
+
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_mux_frame<S>(stream: &mut S, frame: &MuxFrame) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let data = serde_json::to_vec(frame)?;
+    stream.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    stream.write_all(&data).await?;
+    stream.flush().await
+}
+
+/// Estado compartilhado entre a `MuxConnection`, a task de demultiplexação e
+/// cada task de bombeamento de substream.
+struct Shared {
+    writer: Mutex<Box<dyn AsyncWrite + Send + Unpin>>,
+    inbound: Mutex<HashMap<u32, mpsc::UnboundedSender<Vec<u8>>>>,
+    accept_tx: mpsc::UnboundedSender<(String, MuxStream)>,
+}
+
+impl Shared {
+    async fn send_frame(&self, frame: MuxFrame) -> Result<(), MuxError> {
+        let mut writer = self.writer.lock().await;
+        write_mux_frame(&mut *writer, &frame).await.map_err(|e| MuxError::Io(e.to_string()))
+    }
+
+    /// Registra `substream_id` como conhecida, criando seu par
+    /// `tokio::io::duplex` e a task que bombeia dados entre ele e a conexão
+    /// compartilhada. Retorna o lado do aplicativo, pronto para uso.
+    async fn register_substream(self: &Arc<Self>, substream_id: u32) -> MuxStream {
+        let (app_side, internal_side) = io::duplex(SUBSTREAM_BUFFER);
+        let (data_tx, data_rx) = mpsc::unbounded_channel();
+        self.inbound.lock().await.insert(substream_id, data_tx);
+
+        tokio::spawn(pump_substream(substream_id, internal_side, data_rx, self.clone()));
+
+        app_side
+    }
+}
+
+/// Bombeia dados entre o lado interno de uma substream e a conexão
+/// multiplexada compartilhada: tudo que o usuário escreve em seu `MuxStream`
+/// vira frames `Data` mandados pela conexão; tudo que chega da conexão para
+/// esta substream (via `data_rx`) é entregue ao usuário como bytes lidos do
+/// `MuxStream`. Manda `Close` quando o lado do usuário fecha a escrita, e
+/// sinaliza EOF ao usuário quando `data_rx` fecha (a substream foi
+/// encerrada pelo par). Só termina depois que as duas direções acabaram.
+async fn pump_substream(
+    substream_id: u32,
+    internal_side: DuplexStream,
+    mut data_rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    shared: Arc<Shared>,
+) {
+    let (mut internal_read, mut internal_write) = split(internal_side);
+
+    let outbound = async {
+        let mut buf = vec![0u8; SUBSTREAM_BUFFER];
+        loop {
+            let n = match internal_read.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let frame = MuxFrame::Data { substream_id, payload: BASE64.encode(&buf[..n]) };
+            if shared.send_frame(frame).await.is_err() {
+                break;
+            }
+        }
+        let _ = shared.send_frame(MuxFrame::Close { substream_id }).await;
+    };
+
+    let inbound = async {
+        while let Some(bytes) = data_rx.recv().await {
+            if internal_write.write_all(&bytes).await.is_err() {
+                break;
+            }
+        }
+        // `data_rx` fechou (o par encerrou esta substream): desliga o lado
+        // de escrita agora, em vez de esperar `outbound` também terminar,
+        // para que quem estiver lendo do `MuxStream` veja EOF imediatamente.
+        let _ = internal_write.shutdown().await;
+    };
+
+    tokio::join!(outbound, inbound);
+    shared.inbound.lock().await.remove(&substream_id);
+}
+
+/// Lê frames multiplexados continuamente e os distribui: `Open` registra uma
+/// nova substream e a entrega a quem estiver chamando `accept_substream`;
+/// `Data` encaminha o payload para a task de bombeamento da substream
+/// correspondente; `Close` remove a substream do mapa (a task de bombeamento
+/// percebe isso quando `data_rx` fecha). Encerra quando a conexão subjacente
+/// fecha ou falha.
+async fn demux_loop<R>(mut read_half: R, shared: Arc<Shared>)
+where
+    R: AsyncRead + Unpin,
+{
+    loop {
+        let frame = match read_mux_frame(&mut read_half).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) | Err(_) => break,
+        };
+
+        match frame {
+            MuxFrame::Open { substream_id, name } => {
+                let app_side = shared.register_substream(substream_id).await;
+                if shared.accept_tx.send((name, app_side)).is_err() {
+                    break;
+                }
+            }
+            MuxFrame::Data { substream_id, payload } => {
+                let Ok(bytes) = BASE64.decode(&payload) else { continue };
+                let sender = shared.inbound.lock().await.get(&substream_id).cloned();
+                if let Some(sender) = sender {
+                    let _ = sender.send(bytes);
+                }
+            }
+            MuxFrame::Close { substream_id } => {
+                shared.inbound.lock().await.remove(&substream_id);
+            }
+        }
+    }
+}
+
+/// Um transporte multiplexado sobre uma única conexão onion: permite abrir
+/// substreams lógicas nomeadas e aceitar as que o par abrir, em vez de cada
+/// funcionalidade precisar de uma conexão onion própria.
+pub struct MuxConnection {
+    shared: Arc<Shared>,
+    next_id: AtomicU32,
+    accept_rx: Mutex<mpsc::UnboundedReceiver<(String, MuxStream)>>,
+}
+
+impl MuxConnection {
+    /// Assume `stream` (uma conexão onion já aceita ou já conectada) como o
+    /// transporte multiplexado inteiro e começa imediatamente a processar
+    /// frames em segundo plano. `is_client` só decide a paridade dos ids
+    /// gerados localmente (quem conecta usa ids ímpares, quem aceita usa
+    /// ids pares), para que os dois lados nunca escolham o mesmo id ao abrir
+    /// substreams ao mesmo tempo.
+    pub fn new<S>(stream: S, is_client: bool) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (read_half, write_half) = split(stream);
+        let (accept_tx, accept_rx) = mpsc::unbounded_channel();
+
+        let shared = Arc::new(Shared {
+            writer: Mutex::new(Box::new(write_half)),
+            inbound: Mutex::new(HashMap::new()),
+            accept_tx,
+        });
+
+        tokio::spawn(demux_loop(read_half, shared.clone()));
+
+        Self {
+            shared,
+            next_id: AtomicU32::new(if is_client { 1 } else { 0 }),
+            accept_rx: Mutex::new(accept_rx),
+        }
+    }
+
+    /// Abre uma nova substream rotulada `name` e a anuncia ao par. Retorna
+    /// imediatamente, sem esperar nenhuma confirmação: a substream só passa
+    /// a existir para quem recebe quando o `Open` chega.
+    pub async fn open_substream(&self, name: &str) -> Result<MuxStream, MuxError> {
+        let substream_id = self.next_id.fetch_add(2, Ordering::SeqCst);
+        self.shared.send_frame(MuxFrame::Open { substream_id, name: name.to_string() }).await?;
+        Ok(self.shared.register_substream(substream_id).await)
+    }
+
+    /// Espera a próxima substream que o par abrir, retornando seu nome
+    /// lógico junto com a stream já pronta para uso.
+    pub async fn accept_substream(&self) -> Result<(String, MuxStream), MuxError> {
+        self.accept_rx.lock().await.recv().await.ok_or(MuxError::Closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_open_and_accept_named_substream() {
+        let (client_conn, server_conn) = tokio::io::duplex(16 * 1024);
+        let client = MuxConnection::new(client_conn, true);
+        let server = MuxConnection::new(server_conn, false);
+
+        let mut client_stream = client.open_substream(SUBSTREAM_FILE).await.unwrap();
+        let (name, mut server_stream) = server.accept_substream().await.unwrap();
+        assert_eq!(name, SUBSTREAM_FILE);
+
+        client_stream.write_all(b"oi servidor").await.unwrap();
+        let mut buf = [0u8; 32];
+        let n = server_stream.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"oi servidor");
+    }
+
+    #[tokio::test]
+    async fn test_multiple_substreams_are_independent() {
+        let (client_conn, server_conn) = tokio::io::duplex(16 * 1024);
+        let client = MuxConnection::new(client_conn, true);
+        let server = MuxConnection::new(server_conn, false);
+
+        let mut chat_stream = client.open_substream(SUBSTREAM_CHAT).await.unwrap();
+        let mut file_stream = client.open_substream(SUBSTREAM_FILE).await.unwrap();
+
+        let (first_name, mut first_stream) = server.accept_substream().await.unwrap();
+        let (second_name, mut second_stream) = server.accept_substream().await.unwrap();
+
+        chat_stream.write_all(b"mensagem de chat").await.unwrap();
+        file_stream.write_all(b"conteudo de arquivo").await.unwrap();
+
+        let mut streams_by_name: HashMap<String, MuxStream> = HashMap::new();
+        streams_by_name.insert(first_name, first_stream);
+        streams_by_name.insert(second_name, second_stream);
+
+        let mut buf = [0u8; 64];
+        let chat_server = streams_by_name.get_mut(SUBSTREAM_CHAT).unwrap();
+        let n = chat_server.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"mensagem de chat");
+
+        let mut buf2 = [0u8; 64];
+        let file_server = streams_by_name.get_mut(SUBSTREAM_FILE).unwrap();
+        let n2 = file_server.read(&mut buf2).await.unwrap();
+        assert_eq!(&buf2[..n2], b"conteudo de arquivo");
+    }
+
+    #[tokio::test]
+    async fn test_closing_a_substream_surfaces_as_eof_on_the_peer() {
+        let (client_conn, server_conn) = tokio::io::duplex(16 * 1024);
+        let client = MuxConnection::new(client_conn, true);
+        let server = MuxConnection::new(server_conn, false);
+
+        let client_stream = client.open_substream(SUBSTREAM_PRESENCE).await.unwrap();
+        let (_name, mut server_stream) = server.accept_substream().await.unwrap();
+
+        drop(client_stream);
+
+        let mut buf = [0u8; 8];
+        let n = server_stream.read(&mut buf).await.unwrap();
+        assert_eq!(n, 0);
+    }
+}
@@ -0,0 +1,221 @@
+//! Log de operações do catálogo de contatos, no estilo Bayou: em vez de
+//! sobrescrever o estado a cada mutação, cada mutação vira uma operação
+//! timestampada que é anexada a um log ordenado. O estado materializado é
+//! sempre o último checkpoint conhecido com as operações posteriores a ele
+//! reaplicadas por cima, o que permite que dois dispositivos com a mesma
+//! identidade troquem seus logs e convirjam para o mesmo catálogo via
+//! `OpLog::sync`, mesmo tendo feito alterações offline e fora de ordem.
+//!
+//! Este módulo só conhece a estrutura do log e como reaplicá-lo; a
+//! persistência em disco e a cifragem de cada blob ficam por conta de
+//! `storage::SecureStorage`, que usa a mesma chave derivada da identidade
+//! para selar tanto o checkpoint quanto o log inteiro.
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::Fingerprint;
+use crate::storage::StoredContact;
+
+/// A cada `KEEP_STATE_EVERY` operações acumuladas desde o último checkpoint,
+/// o estado é consolidado em um novo checkpoint e as operações já
+/// incorporadas a ele são descartadas do log.
+pub const KEEP_STATE_EVERY: usize = 64;
+
+/// Uma mutação individual do catálogo de contatos.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ContactOp {
+    AddContact { address: String, nickname: String, fingerprint: Fingerprint },
+    RemoveContact { address: String },
+    Rename { address: String, nickname: String },
+}
+
+/// Uma operação com o timestamp (microssegundos desde a epoch) em que foi
+/// criada. O timestamp é o que ordena o replay e o que permite mesclar logs
+/// de dois dispositivos sem um relógio lógico compartilhado.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimestampedOp {
+    pub timestamp: i64,
+    pub op: ContactOp,
+}
+
+/// O log de operações do catálogo de contatos: um checkpoint consolidado
+/// mais as operações aplicadas depois dele.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpLog {
+    pub checkpoint_timestamp: i64,
+    pub checkpoint_state: Vec<StoredContact>,
+    pub operations: Vec<TimestampedOp>,
+}
+
+impl OpLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cria um log a partir de um catálogo já existente, tratado como um
+    /// checkpoint inicial sem histórico de operações (usado na migração de
+    /// um `contacts.json` legado).
+    pub fn from_snapshot(state: Vec<StoredContact>, timestamp: i64) -> Self {
+        Self { checkpoint_timestamp: timestamp, checkpoint_state: state, operations: Vec::new() }
+    }
+
+    /// Reaplica o checkpoint e as operações pendentes para reconstruir o
+    /// catálogo de contatos atual.
+    pub fn materialize(&self) -> Vec<StoredContact> {
+        let mut state = self.checkpoint_state.clone();
+        for timestamped in &self.operations {
+            Self::apply(&mut state, &timestamped.op, timestamped.timestamp);
+        }
+        state
+    }
+
+    fn apply(state: &mut Vec<StoredContact>, op: &ContactOp, timestamp: i64) {
+        match op {
+            ContactOp::AddContact { address, nickname, fingerprint } => {
+                state.retain(|c| &c.address != address);
+                state.push(StoredContact {
+                    address: address.clone(),
+                    nickname: nickname.clone(),
+                    fingerprint: fingerprint.clone(),
+                    added_at: timestamp,
+                });
+            }
+            ContactOp::RemoveContact { address } => {
+                state.retain(|c| &c.address != address);
+            }
+            ContactOp::Rename { address, nickname } => {
+                if let Some(contact) = state.iter_mut().find(|c| &c.address == address) {
+                    contact.nickname = nickname.clone();
+                }
+            }
+        }
+    }
+
+    /// Anexa uma operação ao log. Compacta automaticamente em um novo
+    /// checkpoint quando o log acumula `KEEP_STATE_EVERY` operações.
+    pub fn append(&mut self, op: ContactOp, timestamp: i64) {
+        self.operations.push(TimestampedOp { timestamp, op });
+        if self.operations.len() >= KEEP_STATE_EVERY {
+            self.compact();
+        }
+    }
+
+    /// Consolida todas as operações pendentes em um novo checkpoint e limpa
+    /// as operações já incorporadas a ele.
+    fn compact(&mut self) {
+        if let Some(last) = self.operations.last() {
+            self.checkpoint_timestamp = last.timestamp;
+        }
+        self.checkpoint_state = self.materialize();
+        self.operations.clear();
+    }
+
+    /// Mescla o log de outro dispositivo neste, convergindo para o mesmo
+    /// catálogo de contatos independentemente da ordem em que cada lado viu
+    /// as mutações: parte do checkpoint mais recente entre os dois lados,
+    /// intercala as operações de ambos que são posteriores a esse checkpoint
+    /// (deduplicadas), ordena por timestamp e reaplica.
+    pub fn sync(&mut self, other: &OpLog) {
+        let (base_state, base_timestamp) = if other.checkpoint_timestamp > self.checkpoint_timestamp {
+            (other.checkpoint_state.clone(), other.checkpoint_timestamp)
+        } else {
+            (self.checkpoint_state.clone(), self.checkpoint_timestamp)
+        };
+
+        let mut merged: Vec<TimestampedOp> = Vec::new();
+        for timestamped in self.operations.iter().chain(other.operations.iter()) {
+            if timestamped.timestamp > base_timestamp && !merged.contains(timestamped) {
+                merged.push(timestamped.clone());
+            }
+        }
+        merged.sort_by_key(|timestamped| timestamped.timestamp);
+
+        self.checkpoint_state = base_state;
+        self.checkpoint_timestamp = base_timestamp;
+        self.operations = merged;
+
+        if self.operations.len() >= KEEP_STATE_EVERY {
+            self.compact();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Fingerprint;
+
+    fn fp(s: &str) -> Fingerprint {
+        Fingerprint::new(s.to_string())
+    }
+
+    #[test]
+    fn test_append_and_materialize() {
+        let mut log = OpLog::new();
+        log.append(ContactOp::AddContact {
+            address: "a.onion".to_string(),
+            nickname: "Alice".to_string(),
+            fingerprint: fp("AAAA"),
+        }, 1);
+        log.append(ContactOp::AddContact {
+            address: "b.onion".to_string(),
+            nickname: "Bob".to_string(),
+            fingerprint: fp("BBBB"),
+        }, 2);
+        log.append(ContactOp::RemoveContact { address: "a.onion".to_string() }, 3);
+
+        let contacts = log.materialize();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].address, "b.onion");
+    }
+
+    #[test]
+    fn test_compacts_after_keep_state_every_operations() {
+        let mut log = OpLog::new();
+        for i in 0..KEEP_STATE_EVERY {
+            log.append(ContactOp::AddContact {
+                address: format!("c{i}.onion"),
+                nickname: format!("Contato {i}"),
+                fingerprint: fp("CCCC"),
+            }, i as i64);
+        }
+        assert!(log.operations.is_empty());
+        assert_eq!(log.checkpoint_state.len(), KEEP_STATE_EVERY);
+        assert_eq!(log.materialize().len(), KEEP_STATE_EVERY);
+    }
+
+    #[test]
+    fn test_sync_converges_two_divergent_logs() {
+        let mut base = OpLog::new();
+        base.append(ContactOp::AddContact {
+            address: "shared.onion".to_string(),
+            nickname: "Compartilhado".to_string(),
+            fingerprint: fp("DDDD"),
+        }, 1);
+
+        let mut device_a = base.clone();
+        device_a.append(ContactOp::AddContact {
+            address: "only-a.onion".to_string(),
+            nickname: "Só em A".to_string(),
+            fingerprint: fp("EEEE"),
+        }, 2);
+
+        let mut device_b = base.clone();
+        device_b.append(ContactOp::AddContact {
+            address: "only-b.onion".to_string(),
+            nickname: "Só em B".to_string(),
+            fingerprint: fp("FFFF"),
+        }, 3);
+
+        device_a.sync(&device_b);
+        device_b.sync(&device_a);
+
+        let mut addrs_a: Vec<String> = device_a.materialize().into_iter().map(|c| c.address).collect();
+        let mut addrs_b: Vec<String> = device_b.materialize().into_iter().map(|c| c.address).collect();
+        addrs_a.sort();
+        addrs_b.sort();
+
+        assert_eq!(addrs_a, addrs_b);
+        assert_eq!(addrs_a, vec!["only-a.onion", "only-b.onion", "shared.onion"]);
+    }
+}
@@ -3,6 +3,10 @@
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use sodiumoxide::crypto::kx;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use crate::crypto::{Crypto, Fingerprint, IdentityKeyPair, PublicKey, SessionKeys};
 //use log::{info};
 
 #[derive(Error, Debug)]
@@ -19,6 +23,8 @@ pub enum ProtocolError {
     InvalidFormat,
     #[error("Encryption error: {0}")]
     EncryptionError(String),
+    #[error("Fingerprint mismatch: o par não é quem esperávamos")]
+    FingerprintMismatch,
 }
 
 /// Message types
@@ -79,6 +85,28 @@ pub struct HandshakeMessage {
     pub nonce: String,
 }
 
+/// Mensagem do handshake autenticado: carrega a chave pública X25519
+/// efêmera da sessão (forward secrecy) junto com a chave pública de
+/// identidade de longo prazo de quem envia. O par não assina nada
+/// explicitamente; em vez disso, `run_client_handshake`/`run_server_handshake`
+/// combinam o DH efêmero com um segundo DH estático entre as identidades de
+/// longo prazo (ver `Crypto::static_shared_secret`), de forma que só quem
+/// possui a chave secreta de identidade reivindicada consegue derivar as
+/// chaves de sessão corretas — uma autenticação implícita, sem precisar de
+/// uma assinatura separada.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthHandshakeMessage {
+    /// Chave pública X25519 efêmera desta sessão
+    pub ephemeral_public_key: String,
+    /// Chave pública X25519 de identidade de longo prazo de quem envia
+    pub identity_public_key: String,
+    /// Nonce aleatório desta mensagem. Os dois nonces trocados (cliente e
+    /// servidor) entram na `info` do HKDF em
+    /// `Crypto::derive_authenticated_session_keys`, amarrando as chaves de
+    /// sessão derivadas a esta transcrição específica do handshake.
+    pub nonce: String,
+}
+
 /// Protocol frame for transmission
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtocolFrame {
@@ -178,6 +206,99 @@ impl ChatProtocol {
         }
     }
 
+    /// Split `payload` into one or more frames. Payloads of at most
+    /// `max_fragment_size` bytes are sent as a single `FrameType::Single`
+    /// frame; larger ones are split into byte chunks of at most
+    /// `max_fragment_size` bytes and each chunk is base64-encoded (so a
+    /// split inside a multi-byte UTF-8 character still gives a valid
+    /// payload; the encoded payload is about 4/3 of the chunk size),
+    /// tagged `FirstFragment` / `MiddleFragment` / `LastFragment` in order.
+    pub fn fragment_message(&self, payload: String) -> Vec<ProtocolFrame> {
+        use sodiumoxide::crypto::hash::sha256;
+        use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+
+        let bytes = payload.as_bytes();
+        if bytes.len() <= self.max_fragment_size {
+            return vec![self.create_frame(payload)];
+        }
+
+        let chunks: Vec<&[u8]> = bytes.chunks(self.max_fragment_size).collect();
+        let last = chunks.len() - 1;
+
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let frame_type = if i == 0 {
+                    FrameType::FirstFragment
+                } else if i == last {
+                    FrameType::LastFragment
+                } else {
+                    FrameType::MiddleFragment
+                };
+                let chunk_payload = BASE64.encode(chunk);
+                let checksum = BASE64.encode(sha256::hash(chunk_payload.as_bytes()).as_ref());
+                ProtocolFrame {
+                    frame_type,
+                    payload: chunk_payload,
+                    checksum,
+                }
+            })
+            .collect()
+    }
+
+    /// Inverse of `fragment_message`: validates `frame`'s checksum, then
+    /// either returns its payload immediately (`Single`) or queues it in
+    /// `pending_fragments` until a `LastFragment` arrives, at which point
+    /// the queued chunks are concatenated in order and returned. Fragments
+    /// arriving out of sequence (a `Middle`/`Last` with no preceding
+    /// `First`, or a second `First` before the previous one completed) are
+    /// rejected rather than silently corrupting the reassembly.
+    pub fn push_fragment(&mut self, frame: ProtocolFrame) -> Result<Option<String>, ProtocolError> {
+        use sodiumoxide::crypto::hash::sha256;
+        use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+
+        let expected_checksum = BASE64.encode(sha256::hash(frame.payload.as_bytes()).as_ref());
+        if expected_checksum != frame.checksum {
+            return Err(ProtocolError::InvalidFormat);
+        }
+
+        match frame.frame_type {
+            FrameType::Single => Ok(Some(frame.payload)),
+            FrameType::FirstFragment => {
+                if !self.pending_fragments.is_empty() {
+                    return Err(ProtocolError::InvalidFormat);
+                }
+                self.pending_fragments.push_back(frame);
+                Ok(None)
+            }
+            FrameType::MiddleFragment => {
+                if self.pending_fragments.is_empty() {
+                    return Err(ProtocolError::InvalidFormat);
+                }
+                self.pending_fragments.push_back(frame);
+                Ok(None)
+            }
+            FrameType::LastFragment => {
+                if self.pending_fragments.is_empty() {
+                    return Err(ProtocolError::InvalidFormat);
+                }
+                self.pending_fragments.push_back(frame);
+
+                let mut bytes = Vec::new();
+                for fragment in self.pending_fragments.drain(..) {
+                    let chunk = BASE64.decode(&fragment.payload)
+                        .map_err(|_| ProtocolError::InvalidFormat)?;
+                    bytes.extend_from_slice(&chunk);
+                }
+
+                String::from_utf8(bytes)
+                    .map(Some)
+                    .map_err(|_| ProtocolError::InvalidFormat)
+            }
+        }
+    }
+
     /// Serialize a frame for transmission
     pub fn serialize_frame(&self, frame: &ProtocolFrame) -> Result<String, ProtocolError> {
         serde_json::to_string(frame)
@@ -264,6 +385,7 @@ impl Default for ConnectionState {
 }
 
 /// P2P Connection
+#[derive(Clone)]
 pub struct P2PConnection {
     /// Remote address
     pub remote_address: String,
@@ -298,6 +420,188 @@ impl P2PConnection {
     }
 }
 
+/// Tamanho máximo aceito para um único frame length-delimited, para que um
+/// par malicioso não force a alocação de um buffer arbitrariamente grande
+/// mandando um prefixo de tamanho absurdo.
+pub const MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// Lê um `ProtocolFrame` length-delimited (prefixo de 4 bytes big-endian com
+/// o tamanho, seguido da codificação JSON do frame). Retorna `Ok(None)` em
+/// EOF limpo antes de qualquer byte de um novo frame chegar.
+pub async fn read_frame<S>(stream: &mut S) -> std::io::Result<Option<ProtocolFrame>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut len_buf = [0u8; 4];
+    match stream.read_exact(&mut len_buf).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "frame excede o tamanho máximo aceito"));
+    }
+
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).await?;
+
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Escreve um `ProtocolFrame` em `stream`, prefixado do mesmo jeito que
+/// `read_frame` espera ler de volta.
+pub async fn write_frame<S>(stream: &mut S, frame: &ProtocolFrame) -> std::io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let data = serde_json::to_vec(frame)?;
+    stream.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    stream.write_all(&data).await?;
+    stream.flush().await
+}
+
+/// Monta e envia o `AuthHandshakeMessage` próprio, retornando o par de chaves
+/// efêmero e o nonce gerados para esta sessão (necessários depois para
+/// derivar as chaves finais — ver `Crypto::derive_authenticated_session_keys`).
+/// Compartilhado por `run_client_handshake`/`run_server_handshake`.
+async fn send_auth_handshake<S>(
+    stream: &mut S,
+    protocol: &ChatProtocol,
+    identity: &IdentityKeyPair,
+) -> Result<(kx::PublicKey, kx::SecretKey, Vec<u8>), ProtocolError>
+where
+    S: AsyncWrite + Unpin,
+{
+    let (ephemeral_pk, ephemeral_sk) = kx::gen_keypair();
+    let nonce = Crypto::random_bytes(16);
+    let message = AuthHandshakeMessage {
+        ephemeral_public_key: BASE64.encode(ephemeral_pk.as_ref()),
+        identity_public_key: identity.public_key.clone(),
+        nonce: BASE64.encode(&nonce),
+    };
+    let payload = serde_json::to_string(&message).map_err(|_| ProtocolError::InvalidFormat)?;
+    let frame = protocol.create_frame(payload);
+    write_frame(stream, &frame).await
+        .map_err(|e| ProtocolError::SendFailed(e.to_string()))?;
+
+    Ok((ephemeral_pk, ephemeral_sk, nonce))
+}
+
+/// Lê e valida o `AuthHandshakeMessage` do par, conferindo o fingerprint da
+/// identidade reivindicada contra `expected_fingerprint` quando fornecido (o
+/// handshake aborta com `ProtocolError::FingerprintMismatch` se não bater).
+/// Também devolve o nonce do par, para ser amarrado às chaves de sessão em
+/// `Crypto::derive_authenticated_session_keys`. Compartilhado por
+/// `run_client_handshake`/`run_server_handshake`.
+async fn receive_auth_handshake<S>(
+    stream: &mut S,
+    expected_fingerprint: Option<&Fingerprint>,
+) -> Result<(kx::PublicKey, PublicKey, Vec<u8>), ProtocolError>
+where
+    S: AsyncRead + Unpin,
+{
+    let frame = read_frame(stream).await
+        .map_err(|e| ProtocolError::ReceiveFailed(e.to_string()))?
+        .ok_or_else(|| ProtocolError::HandshakeFailed("conexão encerrada durante o handshake".to_string()))?;
+    let message: AuthHandshakeMessage = serde_json::from_str(&frame.payload)
+        .map_err(|_| ProtocolError::InvalidFormat)?;
+
+    let ephemeral_pk_bytes = BASE64.decode(&message.ephemeral_public_key)
+        .map_err(|e| ProtocolError::HandshakeFailed(e.to_string()))?;
+    let ephemeral_pk = kx::PublicKey::from_slice(&ephemeral_pk_bytes)
+        .ok_or_else(|| ProtocolError::HandshakeFailed("chave efêmera do par inválida".to_string()))?;
+
+    let identity_pk_bytes = BASE64.decode(&message.identity_public_key)
+        .map_err(|e| ProtocolError::HandshakeFailed(e.to_string()))?;
+    let identity_pk = PublicKey::from_slice(&identity_pk_bytes)
+        .ok_or_else(|| ProtocolError::HandshakeFailed("chave de identidade do par inválida".to_string()))?;
+
+    if let Some(expected) = expected_fingerprint {
+        if !expected.verify(&identity_pk) {
+            return Err(ProtocolError::FingerprintMismatch);
+        }
+    }
+
+    let nonce = BASE64.decode(&message.nonce)
+        .map_err(|e| ProtocolError::HandshakeFailed(e.to_string()))?;
+
+    Ok((ephemeral_pk, identity_pk, nonce))
+}
+
+/// Resultado de um handshake autenticado: as chaves de sessão derivadas e a
+/// chave pública de identidade de longo prazo que o par apresentou. Exposta
+/// separadamente das chaves de sessão para que o chamador possa aplicar sua
+/// própria política de confiança (ex.: trust-on-first-use em `conversar`)
+/// sobre o fingerprint correspondente, além da checagem de
+/// `expected_fingerprint` já feita durante o próprio handshake.
+#[derive(Debug, Clone)]
+pub struct HandshakeOutcome {
+    pub session_keys: SessionKeys,
+    pub peer_identity_key: PublicKey,
+}
+
+/// Lado cliente (quem inicia a conexão) do handshake autenticado: manda o
+/// próprio `AuthHandshakeMessage` primeiro, depois lê o do par. Deriva as
+/// chaves de sessão finais combinando o DH efêmero (via
+/// `Crypto::client_session_keys`) com o DH estático entre as identidades de
+/// longo prazo, e aborta se o fingerprint do par não bater com
+/// `expected_fingerprint`.
+pub async fn run_client_handshake<S>(
+    stream: &mut S,
+    protocol: &ChatProtocol,
+    identity: &IdentityKeyPair,
+    expected_fingerprint: Option<&Fingerprint>,
+) -> Result<HandshakeOutcome, ProtocolError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (ephemeral_pk, ephemeral_sk, client_nonce) = send_auth_handshake(stream, protocol, identity).await?;
+    let (peer_ephemeral_pk, peer_identity_pk, server_nonce) =
+        receive_auth_handshake(stream, expected_fingerprint).await?;
+
+    let ephemeral_session = Crypto::client_session_keys(&ephemeral_pk, &ephemeral_sk, &peer_ephemeral_pk)
+        .map_err(|e| ProtocolError::HandshakeFailed(e.to_string()))?;
+    let static_secret = Crypto::static_shared_secret(identity, &peer_identity_pk)
+        .map_err(|e| ProtocolError::HandshakeFailed(e.to_string()))?;
+
+    let session_keys = Crypto::derive_authenticated_session_keys(
+        &ephemeral_session, &static_secret, &client_nonce, &server_nonce,
+    );
+    Ok(HandshakeOutcome { session_keys, peer_identity_key: peer_identity_pk })
+}
+
+/// Lado servidor (quem aceitou a conexão) do handshake autenticado: lê o
+/// `AuthHandshakeMessage` do par primeiro, depois responde com o próprio.
+/// Simétrico a `run_client_handshake`, mas usando `Crypto::server_session_keys`
+/// para o DH efêmero.
+pub async fn run_server_handshake<S>(
+    stream: &mut S,
+    protocol: &ChatProtocol,
+    identity: &IdentityKeyPair,
+    expected_fingerprint: Option<&Fingerprint>,
+) -> Result<HandshakeOutcome, ProtocolError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (peer_ephemeral_pk, peer_identity_pk, client_nonce) =
+        receive_auth_handshake(stream, expected_fingerprint).await?;
+    let (ephemeral_pk, ephemeral_sk, server_nonce) = send_auth_handshake(stream, protocol, identity).await?;
+
+    let ephemeral_session = Crypto::server_session_keys(&ephemeral_pk, &ephemeral_sk, &peer_ephemeral_pk)
+        .map_err(|e| ProtocolError::HandshakeFailed(e.to_string()))?;
+    let static_secret = Crypto::static_shared_secret(identity, &peer_identity_pk)
+        .map_err(|e| ProtocolError::HandshakeFailed(e.to_string()))?;
+
+    let session_keys = Crypto::derive_authenticated_session_keys(
+        &ephemeral_session, &static_secret, &client_nonce, &server_nonce,
+    );
+    Ok(HandshakeOutcome { session_keys, peer_identity_key: peer_identity_pk })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -321,4 +625,112 @@ mod tests {
         let deserialized = protocol.deserialize_frame(&serialized).unwrap();
         assert_eq!(deserialized.payload, "test payload");
     }
+
+    #[test]
+    fn test_fragment_message_small_payload_stays_single() {
+        let protocol = ChatProtocol::new();
+        let frames = protocol.fragment_message("short".to_string());
+        assert_eq!(frames.len(), 1);
+        assert!(matches!(frames[0].frame_type, FrameType::Single));
+    }
+
+    #[test]
+    fn test_fragment_and_reassemble_large_payload() {
+        let mut protocol = ChatProtocol::new();
+        let payload: String = "x".repeat(3000);
+
+        let frames = protocol.fragment_message(payload.clone());
+        assert!(frames.len() > 1);
+        assert!(matches!(frames[0].frame_type, FrameType::FirstFragment));
+        assert!(matches!(frames.last().unwrap().frame_type, FrameType::LastFragment));
+
+        let mut reassembled = None;
+        for frame in frames {
+            reassembled = protocol.push_fragment(frame).unwrap();
+        }
+        assert_eq!(reassembled, Some(payload));
+    }
+
+    #[test]
+    fn test_push_fragment_rejects_out_of_sequence_middle() {
+        let mut protocol = ChatProtocol::new();
+        let frames = protocol.fragment_message("y".repeat(3000));
+        let middle = frames.into_iter().nth(1).unwrap();
+        assert!(protocol.push_fragment(middle).is_err());
+    }
+
+    #[test]
+    fn test_push_fragment_rejects_second_first_before_completion() {
+        let mut protocol = ChatProtocol::new();
+        let frames = protocol.fragment_message("z".repeat(3000));
+        let first = frames[0].clone();
+        protocol.push_fragment(first.clone()).unwrap();
+        assert!(protocol.push_fragment(first).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_read_frame_roundtrip() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let protocol = ChatProtocol::new();
+        let frame = protocol.create_frame("hello via frame".to_string());
+
+        write_frame(&mut client, &frame).await.unwrap();
+        let received = read_frame(&mut server).await.unwrap().unwrap();
+        assert_eq!(received.payload, "hello via frame");
+    }
+
+    #[tokio::test]
+    async fn test_auth_handshake_client_and_server_agree_on_session_keys() {
+        sodiumoxide::init().ok();
+        let (mut client_stream, mut server_stream) = tokio::io::duplex(8192);
+
+        let client_identity = Crypto::generate_identity();
+        let server_identity = Crypto::generate_identity();
+        let client_protocol = ChatProtocol::new();
+        let server_protocol = ChatProtocol::new();
+
+        let (client_result, server_result) = tokio::join!(
+            run_client_handshake(&mut client_stream, &client_protocol, &client_identity, None),
+            run_server_handshake(&mut server_stream, &server_protocol, &server_identity, None),
+        );
+
+        let client_outcome = client_result.unwrap();
+        let server_outcome = server_result.unwrap();
+
+        let encrypted = Crypto::encrypt(b"oi", client_outcome.session_keys.tx.expose());
+        let decrypted = Crypto::decrypt(&encrypted, server_outcome.session_keys.rx.expose()).unwrap();
+        assert_eq!(decrypted, b"oi");
+
+        assert!(Fingerprint::from_public_key(&client_outcome.peer_identity_key)
+            .verify(&identity_public_key(&server_identity)));
+        assert!(Fingerprint::from_public_key(&server_outcome.peer_identity_key)
+            .verify(&identity_public_key(&client_identity)));
+    }
+
+    fn identity_public_key(identity: &IdentityKeyPair) -> PublicKey {
+        PublicKey::from_slice(&BASE64.decode(&identity.public_key).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_auth_handshake_rejects_fingerprint_mismatch() {
+        sodiumoxide::init().ok();
+        let (mut client_stream, mut server_stream) = tokio::io::duplex(8192);
+
+        let client_identity = Crypto::generate_identity();
+        let server_identity = Crypto::generate_identity();
+        let client_protocol = ChatProtocol::new();
+        let server_protocol = ChatProtocol::new();
+
+        let bogus_identity = Crypto::generate_identity();
+        let bogus_pk_bytes = BASE64.decode(&bogus_identity.public_key).unwrap();
+        let bogus_pk = PublicKey::from_slice(&bogus_pk_bytes).unwrap();
+        let wrong_fingerprint = Fingerprint::from_public_key(&bogus_pk);
+
+        let (client_result, _server_result) = tokio::join!(
+            run_client_handshake(&mut client_stream, &client_protocol, &client_identity, Some(&wrong_fingerprint)),
+            run_server_handshake(&mut server_stream, &server_protocol, &server_identity, None),
+        );
+
+        assert!(matches!(client_result, Err(ProtocolError::FingerprintMismatch)));
+    }
 }
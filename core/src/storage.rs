@@ -2,12 +2,14 @@
 
 use std::fs;
 use std::path::PathBuf;
+use std::sync::OnceLock;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use log::{info, error};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
-use crate::crypto::{self, Fingerprint, IdentityKeyPair};
+use crate::crypto::{self, Fingerprint, IdentityKeyPair, SafePassword, Secret, SessionKey};
+use crate::oplog::{ContactOp, OpLog};
 
 #[derive(Error, Debug)]
 pub enum StorageError {
@@ -23,6 +25,8 @@ pub enum StorageError {
     InvalidPassword,
     #[error("Fingerprint mismatch")]
     FingerprintMismatch,
+    #[error("Contacts store is locked: load or create an identity first")]
+    ContactsLocked,
 }
 
 /// Identidade armazenada de forma segura (criptografada em disco)
@@ -30,6 +34,13 @@ pub enum StorageError {
 pub struct SecureIdentity {
     /// Fingerprint da chave pública (para verificação)
     pub fingerprint: Fingerprint,
+    /// Parâmetros do Argon2id usados para selar `encrypted_data` (salt e
+    /// custo), lidos em `load_identity` em vez de ficarem fixos no código.
+    /// Ausente em arquivos gravados antes do envelope versionado, que usavam
+    /// os parâmetros fixos de `Crypto::encrypt_with_password`; esses ainda
+    /// abrem normalmente, tratados como legado (ver `load_identity`).
+    #[serde(default)]
+    pub kdf: Option<crypto::KdfParams>,
     /// Dados criptografados: contém o IdentityKeyPair em formato JSON
     pub encrypted_data: String, // base64
 }
@@ -46,6 +57,11 @@ pub struct StoredContact {
 /// Gerenciador de armazenamento seguro
 pub struct SecureStorage {
     storage_dir: PathBuf,
+    /// Chave de dados do cofre de contatos, derivada da identidade por
+    /// `Crypto::derive_contacts_key` assim que `create_identity`/`load_identity`
+    /// decifram o par de chaves. Fica em cache aqui para que o restante das
+    /// operações de contatos não precisem receber a identidade de novo.
+    contacts_key: OnceLock<Secret<SessionKey>>,
 }
 
 impl SecureStorage {
@@ -53,12 +69,20 @@ impl SecureStorage {
         let storage_dir = dirs::data_local_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("torchat_paste");
+        Self::with_storage_dir(storage_dir)
+    }
 
+    /// Cria um storage apontando para um diretório específico, em vez do
+    /// diretório de dados do usuário. Usado pelos testes para que cada um
+    /// opere sobre seus próprios `identity.enc`/`oplog.enc`, em vez de
+    /// compartilhar (e disputar, sob `cargo test` em paralelo) o storage
+    /// real do usuário que `new()` usaria.
+    pub fn with_storage_dir(storage_dir: PathBuf) -> Self {
         if let Err(e) = fs::create_dir_all(&storage_dir) {
             error!("Failed to create storage directory: {}", e);
         }
 
-        Self { storage_dir }
+        Self { storage_dir, contacts_key: OnceLock::new() }
     }
 
     /// Verifica se a identidade existe (o arquivo)
@@ -67,7 +91,7 @@ impl SecureStorage {
     }
 
     /// Cria uma nova identidade e a salva criptografada com a senha
-    pub fn create_identity(&self, password: &str) -> Result<Fingerprint, StorageError> {
+    pub fn create_identity(&self, password: &SafePassword) -> Result<Fingerprint, StorageError> {
         let keypair = crypto::Crypto::generate_identity();
         let fingerprint = {
             let pk_bytes = BASE64.decode(&keypair.public_key)
@@ -77,14 +101,15 @@ impl SecureStorage {
             crypto::Fingerprint::from_public_key(&pk)
         };
 
-        let plain = serde_json::to_string(&keypair)
+        let plain = keypair.to_sealed_json()
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
-        let encrypted = crypto::Crypto::encrypt_with_password(plain.as_bytes(), password)
+        let (kdf, encrypted) = crypto::Crypto::seal_identity(plain.as_bytes(), password)
             .map_err(|e| StorageError::EncryptionError(e.to_string()))?;
 
         let secure_id = SecureIdentity {
             fingerprint: fingerprint.clone(),
+            kdf: Some(kdf),
             encrypted_data: BASE64.encode(&encrypted),
         };
 
@@ -98,12 +123,14 @@ impl SecureStorage {
         #[cfg(unix)]
         Self::set_permissions_unix(&path);
 
+        self.cache_contacts_key(&keypair)?;
+
         info!("New identity created with fingerprint: {}", fingerprint.formatted());
         Ok(fingerprint)
     }
 
     /// Carrega a identidade (requer senha)
-    pub fn load_identity(&self, password: &str) -> Result<IdentityKeyPair, StorageError> {
+    pub fn load_identity(&self, password: &SafePassword) -> Result<IdentityKeyPair, StorageError> {
         let path = self.storage_dir.join("identity.enc");
         if !path.exists() {
             return Err(StorageError::IdentityNotFound);
@@ -117,10 +144,16 @@ impl SecureStorage {
         let encrypted = BASE64.decode(&secure_id.encrypted_data)
             .map_err(|e| StorageError::EncryptionError(e.to_string()))?;
 
-        let plain = crypto::Crypto::decrypt_with_password(&encrypted, password)
-            .map_err(|_| StorageError::InvalidPassword)?;
+        let plain = match &secure_id.kdf {
+            Some(params) => crypto::Crypto::open_identity(params, &encrypted, password)
+                .map_err(|_| StorageError::InvalidPassword)?,
+            // Arquivo gerado antes do envelope versionado: formato antigo de
+            // `encrypt_with_password`, com salt embutido e custo fixo.
+            None => crypto::Crypto::decrypt_with_password(&encrypted, password)
+                .map_err(|_| StorageError::InvalidPassword)?,
+        };
 
-        let keypair: IdentityKeyPair = serde_json::from_slice(&plain)
+        let keypair = IdentityKeyPair::from_sealed_json(&plain)
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
         // Verifica se o fingerprint corresponde (integridade)
@@ -133,32 +166,61 @@ impl SecureStorage {
             return Err(StorageError::FingerprintMismatch);
         }
 
+        self.cache_contacts_key(&keypair)?;
+
+        // A senha acabou de ser confirmada: aproveita para resselar
+        // identidades legadas ou seladas com um custo abaixo do atual.
+        if let Err(e) = self.rehash_if_needed(&secure_id, &keypair, password) {
+            error!("Failed to rehash identity at rest: {}", e);
+        }
+
         Ok(keypair)
     }
 
+    /// Resela `identity.enc` com os parâmetros de Argon2id atuais quando o
+    /// arquivo é legado (sem `kdf`) ou foi selado com um custo abaixo do
+    /// recomendado por esta versão da crate. Não propaga falhas de escrita
+    /// como erro de carregamento: a identidade já foi decifrada com sucesso,
+    /// e a pior consequência de uma regravação falha é tentar de novo no
+    /// próximo login.
+    fn rehash_if_needed(&self, secure_id: &SecureIdentity, keypair: &IdentityKeyPair, password: &SafePassword) -> Result<(), StorageError> {
+        let up_to_date = match &secure_id.kdf {
+            Some(params) => params.meets_current_cost(),
+            None => false,
+        };
+        if up_to_date {
+            return Ok(());
+        }
+
+        info!("Rehashing identity {} with current KDF parameters", secure_id.fingerprint.formatted());
+        self.create_identity_with_keypair(keypair, password)?;
+        Ok(())
+    }
+
     /// Altera a senha da identidade
-    pub fn change_password(&self, old_password: &str, new_password: &str) -> Result<(), StorageError> {
+    pub fn change_password(&self, old_password: &SafePassword, new_password: &SafePassword) -> Result<(), StorageError> {
         let keypair = self.load_identity(old_password)?;
         self.create_identity_with_keypair(&keypair, new_password)?;
         Ok(())
     }
 
     /// Salva uma identidade existente com nova senha (usado internamente)
-    fn create_identity_with_keypair(&self, keypair: &IdentityKeyPair, password: &str) -> Result<Fingerprint, StorageError> {
+    fn create_identity_with_keypair(&self, keypair: &IdentityKeyPair, password: &SafePassword) -> Result<Fingerprint, StorageError> {
         let pk_bytes = BASE64.decode(&keypair.public_key)
             .map_err(|e| StorageError::EncryptionError(e.to_string()))?;
         let pk = crypto::PublicKey::from_slice(&pk_bytes)
             .ok_or(StorageError::EncryptionError("Invalid public key".to_string()))?;
         let fingerprint = crypto::Fingerprint::from_public_key(&pk);
 
-        let plain = serde_json::to_string(keypair)
+        let plain = keypair.to_sealed_json()
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
-        let encrypted = crypto::Crypto::encrypt_with_password(plain.as_bytes(), password)
+        let (kdf, encrypted) = crypto::Crypto::seal_identity(plain.as_bytes(), password)
             .map_err(|e| StorageError::EncryptionError(e.to_string()))?;
 
         let secure_id = SecureIdentity {
             fingerprint: fingerprint.clone(),
+            kdf: Some(kdf),
             encrypted_data: BASE64.encode(&encrypted),
         };
 
@@ -175,61 +237,187 @@ impl SecureStorage {
         Ok(fingerprint)
     }
 
-    /// Carrega a lista de contatos
-    pub fn load_contacts(&self) -> Result<Vec<StoredContact>, StorageError> {
-        let path = self.storage_dir.join("contacts.json");
-        if !path.exists() {
-            return Ok(Vec::new());
+    /// Deriva e armazena em cache a chave de dados do cofre de contatos a
+    /// partir de uma identidade já decifrada. Chamadas repetidas (ex.: ao
+    /// trocar de senha) são no-op se a chave já estiver em cache.
+    fn cache_contacts_key(&self, keypair: &IdentityKeyPair) -> Result<(), StorageError> {
+        if self.contacts_key.get().is_some() {
+            return Ok(());
         }
-        let content = fs::read_to_string(&path)
-            .map_err(|e| StorageError::IoError(e.to_string()))?;
-        let contacts: Vec<StoredContact> = serde_json::from_str(&content)
-            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-        Ok(contacts)
+        let key = crypto::Crypto::derive_contacts_key(keypair)
+            .map_err(|e| StorageError::EncryptionError(e.to_string()))?;
+        let _ = self.contacts_key.set(Secret::new(key));
+        Ok(())
+    }
+
+    fn contacts_key(&self) -> Result<&SessionKey, StorageError> {
+        self.contacts_key.get()
+            .map(Secret::expose)
+            .ok_or(StorageError::ContactsLocked)
     }
 
-    /// Salva a lista de contatos
-    pub fn save_contacts(&self, contacts: &[StoredContact]) -> Result<(), StorageError> {
-        let path = self.storage_dir.join("contacts.json");
-        let content = serde_json::to_string_pretty(contacts)
+    /// Carrega o log de operações do catálogo de contatos (`oplog.enc`),
+    /// decifrando-o com a chave de dados derivada da identidade. Migra, na
+    /// primeira vez, qualquer `contacts.enc` (catálogo cifrado mas ainda de
+    /// sobrescrita total, de antes do log de operações existir) ou
+    /// `contacts.json` legado em texto claro, tratando o conteúdo encontrado
+    /// como o checkpoint inicial do log.
+    fn load_oplog(&self) -> Result<OpLog, StorageError> {
+        let log_path = self.storage_dir.join("oplog.enc");
+        if log_path.exists() {
+            let key = self.contacts_key()?;
+            let content = fs::read_to_string(&log_path)
+                .map_err(|e| StorageError::IoError(e.to_string()))?;
+            let enc: crypto::EncryptedMessage = serde_json::from_str(&content)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            let plain = crypto::Crypto::decrypt(&enc, key)
+                .map_err(|_| StorageError::EncryptionError("failed to decrypt oplog.enc".to_string()))?;
+            let log: OpLog = serde_json::from_slice(&plain)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            return Ok(log);
+        }
+
+        let enc_contacts_path = self.storage_dir.join("contacts.enc");
+        let legacy_path = self.storage_dir.join("contacts.json");
+
+        if enc_contacts_path.exists() {
+            info!("Migrando contacts.enc (catálogo plano cifrado) para oplog.enc");
+            let key = self.contacts_key()?;
+            let content = fs::read_to_string(&enc_contacts_path)
+                .map_err(|e| StorageError::IoError(e.to_string()))?;
+            let enc: crypto::EncryptedMessage = serde_json::from_str(&content)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            let plain = crypto::Crypto::decrypt(&enc, key)
+                .map_err(|_| StorageError::EncryptionError("failed to decrypt contacts.enc".to_string()))?;
+            let contacts: Vec<StoredContact> = serde_json::from_slice(&plain)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            let log = OpLog::from_snapshot(contacts, chrono::Utc::now().timestamp_micros());
+            self.save_oplog(&log)?;
+            fs::remove_file(&enc_contacts_path)
+                .map_err(|e| StorageError::IoError(e.to_string()))?;
+            return Ok(log);
+        }
+
+        if legacy_path.exists() {
+            info!("Migrando contacts.json legado para oplog.enc");
+            let content = fs::read_to_string(&legacy_path)
+                .map_err(|e| StorageError::IoError(e.to_string()))?;
+            let contacts: Vec<StoredContact> = serde_json::from_str(&content)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            let log = OpLog::from_snapshot(contacts, chrono::Utc::now().timestamp_micros());
+            self.save_oplog(&log)?;
+            fs::remove_file(&legacy_path)
+                .map_err(|e| StorageError::IoError(e.to_string()))?;
+            return Ok(log);
+        }
+
+        Ok(OpLog::new())
+    }
+
+    /// Sela e grava o log de operações em `oplog.enc`.
+    fn save_oplog(&self, log: &OpLog) -> Result<(), StorageError> {
+        let key = self.contacts_key()?;
+        let plain = serde_json::to_vec(log)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let enc = crypto::Crypto::encrypt(&plain, key);
+
+        let path = self.storage_dir.join("oplog.enc");
+        let content = serde_json::to_string_pretty(&enc)
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
         fs::write(&path, content)
             .map_err(|e| StorageError::IoError(e.to_string()))?;
+
+        #[cfg(unix)]
+        Self::set_permissions_unix(&path);
+
         Ok(())
     }
 
-    /// Adiciona um contato (com fingerprint)
+    /// Carrega a lista de contatos, reaplicando o log de operações sobre o
+    /// último checkpoint conhecido.
+    pub fn load_contacts(&self) -> Result<Vec<StoredContact>, StorageError> {
+        Ok(self.load_oplog()?.materialize())
+    }
+
+    /// Adiciona um contato (com fingerprint), anexando uma `ContactOp::AddContact`
+    /// ao log em vez de reescrever o catálogo inteiro.
     pub fn add_contact(&self, address: &str, nickname: &str, fingerprint: Fingerprint) -> Result<(), StorageError> {
-        let mut contacts = self.load_contacts()?;
-        if contacts.iter().any(|c| c.address == address) {
+        let mut log = self.load_oplog()?;
+        if log.materialize().iter().any(|c| c.address == address) {
             return Ok(()); // já existe
         }
-        let contact = StoredContact {
-            address: address.to_string(),
-            nickname: nickname.to_string(),
-            fingerprint,
-            added_at: chrono::Utc::now().timestamp(),
-        };
-        contacts.push(contact);
-        self.save_contacts(&contacts)?;
+        log.append(
+            ContactOp::AddContact {
+                address: address.to_string(),
+                nickname: nickname.to_string(),
+                fingerprint,
+            },
+            chrono::Utc::now().timestamp_micros(),
+        );
+        self.save_oplog(&log)?;
         info!("Contact added: {} ({})", address, nickname);
         Ok(())
     }
 
-    /// Remove um contato
+    /// Remove um contato, anexando uma `ContactOp::RemoveContact` ao log.
     pub fn remove_contact(&self, address: &str) -> Result<(), StorageError> {
-        let mut contacts = self.load_contacts()?;
-        contacts.retain(|c| c.address != address);
-        self.save_contacts(&contacts)?;
+        let mut log = self.load_oplog()?;
+        log.append(
+            ContactOp::RemoveContact { address: address.to_string() },
+            chrono::Utc::now().timestamp_micros(),
+        );
+        self.save_oplog(&log)?;
         info!("Contact removed: {}", address);
         Ok(())
     }
 
+    /// Renomeia um contato, anexando uma `ContactOp::Rename` ao log.
+    pub fn rename_contact(&self, address: &str, nickname: &str) -> Result<(), StorageError> {
+        let mut log = self.load_oplog()?;
+        log.append(
+            ContactOp::Rename { address: address.to_string(), nickname: nickname.to_string() },
+            chrono::Utc::now().timestamp_micros(),
+        );
+        self.save_oplog(&log)?;
+        info!("Contact renamed: {} -> {}", address, nickname);
+        Ok(())
+    }
+
     /// Busca um contato pelo endereço
     pub fn find_contact(&self, address: &str) -> Option<StoredContact> {
         self.load_contacts().ok()?.into_iter().find(|c| c.address == address)
     }
 
+    /// Exporta o log de operações selado (o mesmo blob gravado em
+    /// `oplog.enc`) para ser transferido a outro dispositivo que compartilhe
+    /// esta identidade.
+    pub fn export_oplog(&self) -> Result<Vec<u8>, StorageError> {
+        let log = self.load_oplog()?;
+        let key = self.contacts_key()?;
+        let plain = serde_json::to_vec(&log)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let enc = crypto::Crypto::encrypt(&plain, key);
+        serde_json::to_vec(&enc).map_err(|e| StorageError::SerializationError(e.to_string()))
+    }
+
+    /// Mescla o log de operações de outro dispositivo (obtido via
+    /// `export_oplog` nele) neste log, convergindo para o mesmo catálogo de
+    /// contatos. Como ambos os dispositivos compartilham a mesma identidade,
+    /// a mesma chave derivada decifra o log de ambos os lados.
+    pub fn sync(&self, other_sealed_log: &[u8]) -> Result<(), StorageError> {
+        let key = self.contacts_key()?;
+        let enc: crypto::EncryptedMessage = serde_json::from_slice(other_sealed_log)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let plain = crypto::Crypto::decrypt(&enc, key)
+            .map_err(|_| StorageError::EncryptionError("failed to decrypt peer oplog".to_string()))?;
+        let other: OpLog = serde_json::from_slice(&plain)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        let mut log = self.load_oplog()?;
+        log.sync(&other);
+        self.save_oplog(&log)
+    }
+
     /// Wipe all data (secure delete)
     pub fn wipe_all(&self) -> Result<(), StorageError> {
         let identity_path = self.storage_dir.join("identity.enc");
@@ -243,12 +431,24 @@ impl SecureStorage {
                 .map_err(|e| StorageError::IoError(e.to_string()))?;
         }
 
-        let contacts_path = self.storage_dir.join("contacts.json");
+        let oplog_path = self.storage_dir.join("oplog.enc");
+        if oplog_path.exists() {
+            fs::remove_file(&oplog_path)
+                .map_err(|e| StorageError::IoError(e.to_string()))?;
+        }
+
+        let contacts_path = self.storage_dir.join("contacts.enc");
         if contacts_path.exists() {
             fs::remove_file(&contacts_path)
                 .map_err(|e| StorageError::IoError(e.to_string()))?;
         }
 
+        let legacy_contacts_path = self.storage_dir.join("contacts.json");
+        if legacy_contacts_path.exists() {
+            fs::remove_file(&legacy_contacts_path)
+                .map_err(|e| StorageError::IoError(e.to_string()))?;
+        }
+
         info!("All secure data wiped");
         Ok(())
     }
@@ -274,22 +474,36 @@ impl Default for SecureStorage {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Um `SecureStorage` isolado num diretório temporário próprio, para que
+    /// os testes não disputem (e se destruam via `wipe_all`) o
+    /// `identity.enc`/`oplog.enc` reais de `SecureStorage::new()` quando
+    /// `cargo test` os roda em paralelo.
+    fn test_storage() -> SecureStorage {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir()
+            .join(format!("torchat_paste_test_{}_{}", std::process::id(), n));
+        SecureStorage::with_storage_dir(dir)
+    }
 
     #[test]
     fn test_identity_creation_and_load() {
-        let storage = SecureStorage::new();
-        let password = "test123";
+        let storage = test_storage();
+        let password = SafePassword::new("test123".to_string());
 
         // Cria identidade
-        let fp = storage.create_identity(password).unwrap();
+        let fp = storage.create_identity(&password).unwrap();
         assert!(storage.has_identity());
 
         // Carrega com senha correta
-        let keypair = storage.load_identity(password).unwrap();
+        let keypair = storage.load_identity(&password).unwrap();
         assert_eq!(keypair.public_key.len(), 44); // base64 de 32 bytes
 
         // Carrega com senha errada
-        assert!(storage.load_identity("wrong").is_err());
+        let wrong = SafePassword::new("wrong".to_string());
+        assert!(storage.load_identity(&wrong).is_err());
 
         // Verifica fingerprint
         let pk_bytes = BASE64.decode(&keypair.public_key).unwrap();
@@ -300,4 +514,170 @@ mod tests {
         storage.wipe_all().unwrap();
         assert!(!storage.has_identity());
     }
+
+    #[test]
+    fn test_legacy_identity_without_kdf_envelope_still_opens() {
+        let storage = test_storage();
+        let _ = storage.wipe_all();
+        let password = SafePassword::new("legacy-identity".to_string());
+
+        let keypair = crypto::Crypto::generate_identity();
+        let pk_bytes = BASE64.decode(&keypair.public_key).unwrap();
+        let pk = crypto::PublicKey::from_slice(&pk_bytes).unwrap();
+        let fingerprint = crypto::Fingerprint::from_public_key(&pk);
+
+        // Simula um `identity.enc` gravado antes do envelope versionado:
+        // sem o campo `kdf`, selado com `encrypt_with_password`.
+        let plain = keypair.to_sealed_json().unwrap();
+        let encrypted = crypto::Crypto::encrypt_with_password(plain.as_bytes(), &password).unwrap();
+        let legacy = SecureIdentity {
+            fingerprint: fingerprint.clone(),
+            kdf: None,
+            encrypted_data: BASE64.encode(&encrypted),
+        };
+        let path = storage.storage_dir.join("identity.enc");
+        fs::write(&path, serde_json::to_string_pretty(&legacy).unwrap()).unwrap();
+
+        let loaded = storage.load_identity(&password).unwrap();
+        assert_eq!(loaded.public_key, keypair.public_key);
+
+        // O arquivo legado é resselado automaticamente no load, com os
+        // parâmetros atuais já gravados no envelope.
+        let content = fs::read_to_string(&path).unwrap();
+        let upgraded: SecureIdentity = serde_json::from_str(&content).unwrap();
+        assert!(upgraded.kdf.is_some());
+        assert!(upgraded.kdf.unwrap().meets_current_cost());
+
+        storage.wipe_all().unwrap();
+    }
+
+    #[test]
+    fn test_identity_sealed_with_outdated_cost_is_rehashed_on_load() {
+        let storage = test_storage();
+        let _ = storage.wipe_all();
+        let password = SafePassword::new("needs-rehash".to_string());
+
+        let fp = storage.create_identity(&password).unwrap();
+
+        // Simula uma elevação futura do custo recomendado: resela a mesma
+        // identidade com parâmetros abaixo do que a crate considera atual e
+        // grava esses parâmetros no envelope.
+        use sodiumoxide::crypto::{pwhash::argon2id13, secretbox};
+        let path = storage.storage_dir.join("identity.enc");
+        let content = fs::read_to_string(&path).unwrap();
+        let mut secure_id: SecureIdentity = serde_json::from_str(&content).unwrap();
+        let original = storage.load_identity(&password).unwrap();
+        let mut params = secure_id.kdf.clone().unwrap();
+        params.ops_limit = 1;
+        let salt = argon2id13::Salt::from_slice(&BASE64.decode(&params.salt).unwrap()).unwrap();
+        let mut key = [0u8; secretbox::KEYBYTES];
+        argon2id13::derive_key(&mut key, password.expose().as_bytes(), &salt,
+            argon2id13::OpsLimit(params.ops_limit), argon2id13::MemLimit(params.mem_limit)).unwrap();
+        let key_box = secretbox::Key::from_slice(&key).unwrap();
+        let nonce = secretbox::gen_nonce();
+        let mut sealed = nonce.as_ref().to_vec();
+        sealed.extend_from_slice(&secretbox::seal(original.to_sealed_json().unwrap().as_bytes(), &nonce, &key_box));
+        secure_id.kdf = Some(params);
+        secure_id.encrypted_data = BASE64.encode(&sealed);
+        fs::write(&path, serde_json::to_string_pretty(&secure_id).unwrap()).unwrap();
+
+        let keypair = storage.load_identity(&password).unwrap();
+        let pk_bytes = BASE64.decode(&keypair.public_key).unwrap();
+        let pk = crypto::PublicKey::from_slice(&pk_bytes).unwrap();
+        assert!(fp.verify(&pk));
+
+        let content = fs::read_to_string(&path).unwrap();
+        let rehashed: SecureIdentity = serde_json::from_str(&content).unwrap();
+        assert!(rehashed.kdf.unwrap().meets_current_cost());
+
+        storage.wipe_all().unwrap();
+    }
+
+    #[test]
+    fn test_contacts_are_encrypted_at_rest() {
+        let storage = test_storage();
+        let _ = storage.wipe_all();
+        let password = SafePassword::new("contacts-test".to_string());
+        storage.create_identity(&password).unwrap();
+
+        let fingerprint = Fingerprint::new("AAAAAAAA".to_string());
+        storage.add_contact("secretfriend.onion", "Amigo Secreto", fingerprint).unwrap();
+
+        let enc_path = storage.storage_dir.join("oplog.enc");
+        assert!(enc_path.exists());
+        assert!(!storage.storage_dir.join("contacts.json").exists());
+
+        let raw = fs::read_to_string(&enc_path).unwrap();
+        assert!(!raw.contains("secretfriend"));
+        assert!(!raw.contains("Amigo Secreto"));
+
+        let contacts = storage.load_contacts().unwrap();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].address, "secretfriend.onion");
+        assert_eq!(contacts[0].nickname, "Amigo Secreto");
+
+        storage.wipe_all().unwrap();
+    }
+
+    #[test]
+    fn test_legacy_plaintext_contacts_are_migrated() {
+        let storage = test_storage();
+        let _ = storage.wipe_all();
+
+        let legacy_contact = StoredContact {
+            address: "legacy.onion".to_string(),
+            nickname: "Contato Antigo".to_string(),
+            fingerprint: Fingerprint::new("BBBBBBBB".to_string()),
+            added_at: 0,
+        };
+        let legacy_path = storage.storage_dir.join("contacts.json");
+        fs::write(&legacy_path, serde_json::to_string(&vec![legacy_contact]).unwrap()).unwrap();
+
+        let password = SafePassword::new("migration-test".to_string());
+        storage.create_identity(&password).unwrap();
+
+        let contacts = storage.load_contacts().unwrap();
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].address, "legacy.onion");
+        assert!(!legacy_path.exists());
+        assert!(storage.storage_dir.join("oplog.enc").exists());
+
+        storage.wipe_all().unwrap();
+    }
+
+    #[test]
+    fn test_sync_merges_a_peer_oplog_sealed_with_the_same_contacts_key() {
+        let storage = test_storage();
+        let _ = storage.wipe_all();
+        let password = SafePassword::new("sync-test".to_string());
+        storage.create_identity(&password).unwrap();
+
+        storage.add_contact("shared.onion", "Compartilhado", Fingerprint::new("SSSSSSSS".to_string())).unwrap();
+
+        // Simula o log de um segundo dispositivo que compartilha a mesma
+        // identidade: parte do mesmo ponto e diverge adicionando outro
+        // contato offline, sem nunca ter visto o storage local diretamente.
+        let mut peer_log = storage.load_oplog().unwrap();
+        peer_log.append(
+            ContactOp::AddContact {
+                address: "device-b-only.onion".to_string(),
+                nickname: "Só no outro dispositivo".to_string(),
+                fingerprint: Fingerprint::new("TTTTTTTT".to_string()),
+            },
+            chrono::Utc::now().timestamp_micros(),
+        );
+        let peer_plain = serde_json::to_vec(&peer_log).unwrap();
+        let peer_key = storage.contacts_key().unwrap();
+        let peer_enc = crypto::Crypto::encrypt(&peer_plain, peer_key);
+        let peer_sealed = serde_json::to_vec(&peer_enc).unwrap();
+
+        storage.add_contact("device-a-only.onion", "Só aqui", Fingerprint::new("UUUUUUUU".to_string())).unwrap();
+        storage.sync(&peer_sealed).unwrap();
+
+        let mut addrs: Vec<String> = storage.load_contacts().unwrap().into_iter().map(|c| c.address).collect();
+        addrs.sort();
+        assert_eq!(addrs, vec!["device-a-only.onion", "device-b-only.onion", "shared.onion"]);
+
+        storage.wipe_all().unwrap();
+    }
 }
@@ -1,21 +1,34 @@
 //! Tor Manager - agora com suporte a serviços onion efêmeros para compartilhamento
 
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use log::{info, error};
 use thiserror::Error;
 use serde::{Deserialize, Serialize};
 use futures::stream::StreamExt;
+use tokio::sync::{mpsc, watch};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::fs::File;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use sodiumoxide::crypto::sign;
+use sha3::{Digest, Sha3_256};
+use data_encoding::BASE32_NOPAD;
 
 use arti_client::{TorClient, TorClientConfig};
 use arti_client::onion::service::{OnionServiceConfig, RunningOnionService};
 use tor_rtcompat::PreferredRuntime;
 use tokio_socks::tcp::Socks5Stream;
 
-use axum::{Router, response::IntoResponse};
-use axum::routing::get;
 use hyper::server::conn::Http;
 
+use crate::config::BridgeConfig;
+use crate::crypto::{self, Crypto, EncryptedMessage, SessionKey};
+use crate::protocol::{
+    read_frame, write_frame, ChatProtocol, ConnectionState, Message,
+    MessageType, P2PConnection,
+};
+
 #[derive(Error, Debug)]
 pub enum TorError {
     #[error("Tor client creation failed: {0}")]
@@ -26,12 +39,16 @@ pub enum TorError {
     HiddenServiceCreation(String),
     #[error("Connection failed: {0}")]
     ConnectionFailed(String),
+    #[error("Handshake failed: {0}")]
+    HandshakeFailed(String),
     #[error("Invalid onion address: {0}")]
     InvalidOnionAddress(String),
     #[error("SOCKS5 proxy error: {0}")]
     SocksError(String),
     #[error("Tor not initialized")]
     NotInitialized,
+    #[error("File transfer integrity check failed: expected {expected}, got {actual}")]
+    FileIntegrityMismatch { expected: String, actual: String },
 }
 
 /// Tor connection status
@@ -41,6 +58,8 @@ pub enum TorStatus {
     NotStarted,
     /// Tor is currently bootstrapping (percentage)
     Bootstrapping(u8),
+    /// Tor is bootstrapping through a configured pluggable-transport bridge
+    BootstrappingViaBridge(u8),
     /// Tor is fully operational
     Ready,
     /// Tor encountered an error
@@ -53,9 +72,61 @@ impl Default for TorStatus {
     }
 }
 
+/// Arquivo a ser enviado junto do endereço permanente através de um serviço
+/// onion efêmero (ver `TorManager::create_ephemeral_sharing_service`).
+pub struct FileShare {
+    file_name: String,
+    contents: Vec<u8>,
+    sha3_256_hex: String,
+}
+
+impl FileShare {
+    /// Lê `path` inteiro em memória, computando o digest SHA3-256
+    /// incrementalmente a cada bloco lido, em vez de carregar o arquivo e
+    /// só depois passar por ele de novo para calcular o hash.
+    pub async fn from_path(path: &std::path::Path) -> std::io::Result<Self> {
+        let file_name = path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "arquivo".to_string());
+
+        let mut file = File::open(path).await?;
+        let mut hasher = Sha3_256::new();
+        let mut contents = Vec::new();
+        let mut buf = [0u8; 65536];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            contents.extend_from_slice(&buf[..n]);
+        }
+
+        Ok(Self {
+            file_name,
+            contents,
+            sha3_256_hex: hex::encode(hasher.finalize()),
+        })
+    }
+}
+
+/// Manifesto de um arquivo compartilhado via serviço onion efêmero: nome,
+/// tamanho e digest SHA3-256, computados por `FileShare::from_path` enquanto
+/// o arquivo é lido. É selado e servido em `/manifest` com uma chave que
+/// nunca passa pelo serviço onion (ver `create_ephemeral_sharing_service`),
+/// para que um link adulterado não possa fazer o receptor aceitar bytes
+/// diferentes dos que o remetente anunciou.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileManifest {
+    pub file_name: String,
+    pub size: u64,
+    pub sha3_256: String, // hex
+}
+
 /// Tor Manager - Manages Tor client and hidden service using Arti
 pub struct TorManager {
-    status: TorStatus,
+    /// Current status, also broadcast to anyone holding a `subscribe_status()` receiver
+    status_tx: watch::Sender<TorStatus>,
     /// Our hidden service address (.onion)
     onion_address: Option<String>,
     /// SOCKS5 proxy port (always localhost:9050 with Arti)
@@ -64,22 +135,76 @@ pub struct TorManager {
     tor_client: Option<Arc<TorClient<PreferredRuntime>>>,
     /// The onion service runner (kept alive)
     _onion_service: Option<Arc<RunningOnionService>>,
+    /// Pluggable-transport bridges to try when bootstrapping, in order
+    bridges: Vec<BridgeConfig>,
+    /// Nickname + key directory of the currently active persistent identity,
+    /// kept around so `relaunch_persistent_service` can re-launch the same
+    /// `.onion` address after `shutdown_hidden_service` instead of
+    /// generating a throwaway one.
+    persistent_identity: Option<(String, PathBuf)>,
+    /// Channel inbound chat messages are forwarded to, once a handler has
+    /// been registered via `set_incoming_handler`. The accept loops copy
+    /// this (and `local_identity`) when a service is launched; if either is
+    /// missing at that point, every stream accepted on that service is
+    /// dropped without a handshake.
+    incoming_tx: Option<mpsc::Sender<(P2PConnection, Message)>>,
+    /// Our own long-term identity, registered via `set_local_identity`.
+    /// Needed by the accept loops to run the authenticated handshake
+    /// (`protocol::run_server_handshake`) against inbound connections, the
+    /// same one `conversar`'s client side runs via `run_client_handshake`.
+    local_identity: Option<crypto::IdentityKeyPair>,
 }
 
 impl TorManager {
     pub fn new() -> Self {
+        Self::with_bridges(Vec::new())
+    }
+
+    /// Create a manager that bootstraps through the given bridges, falling
+    /// back to a direct connection when the list is empty.
+    pub fn with_bridges(bridges: Vec<BridgeConfig>) -> Self {
+        let (status_tx, _rx) = watch::channel(TorStatus::NotStarted);
         Self {
-            status: TorStatus::NotStarted,
+            status_tx,
             onion_address: None,
             socks_addr: Some(SocketAddr::from(([127, 0, 0, 1], 9050))),
             tor_client: None,
             _onion_service: None,
+            bridges,
+            persistent_identity: None,
+            incoming_tx: None,
+            local_identity: None,
         }
     }
 
+    /// Replace the configured bridge list (takes effect on the next bootstrap)
+    pub fn set_bridges(&mut self, bridges: Vec<BridgeConfig>) {
+        self.bridges = bridges;
+    }
+
+    /// Register the channel that inbound chat messages accepted on our
+    /// hidden services are forwarded to, so the application layer can react
+    /// to them instead of having them silently dropped.
+    pub fn set_incoming_handler(&mut self, tx: mpsc::Sender<(P2PConnection, Message)>) {
+        self.incoming_tx = Some(tx);
+    }
+
+    /// Register our own long-term identity, so that inbound connections
+    /// accepted on our hidden services can complete the authenticated
+    /// handshake (`run_server_handshake`) instead of being dropped for lack
+    /// of a local identity to handshake with.
+    pub fn set_local_identity(&mut self, identity: crypto::IdentityKeyPair) {
+        self.local_identity = Some(identity);
+    }
+
     /// Get current status
-    pub fn get_status(&self) -> &TorStatus {
-        &self.status
+    pub fn get_status(&self) -> TorStatus {
+        self.status_tx.borrow().clone()
+    }
+
+    /// Subscribe to status changes, e.g. to render a live bootstrap progress bar
+    pub fn subscribe_status(&self) -> watch::Receiver<TorStatus> {
+        self.status_tx.subscribe()
     }
 
     /// Get our onion address
@@ -93,8 +218,8 @@ impl TorManager {
     }
 
     /// Set status
-    pub fn set_status(&mut self, status: TorStatus) {
-        self.status = status;
+    pub fn set_status(&self, status: TorStatus) {
+        let _ = self.status_tx.send(status);
     }
 
     /// Validate onion address format (v3)
@@ -116,18 +241,109 @@ impl TorManager {
     }
 
     /// Bootstrap Tor (cria o cliente e aguarda prontidão)
+    ///
+    /// Se houver bridges configuradas, a conexão é feita via pluggable
+    /// transport (obfs4 ou similar); caso contrário, conecta diretamente.
+    ///
+    /// Diferente de antes, o cliente é criado ainda não-bootstrapado e o
+    /// progresso real do bootstrap (via `bootstrap_events()` do Arti) é
+    /// transmitido em `TorStatus::Bootstrapping`/`BootstrappingViaBridge`
+    /// através do canal retornado por `subscribe_status`, em vez de pular
+    /// direto de `NotStarted` para `Ready`.
     pub async fn bootstrap(&mut self) -> Result<(), TorError> {
-        info!("Criando cliente Tor com Arti...");
-        let config = TorClientConfig::default();
-        let tor_client = TorClient::<PreferredRuntime>::create_bootstrapped(config).await
-            .map_err(|e| TorError::BootstrapFailed(e.to_string()))?;
+        let config = self.build_client_config()?;
+        let via_bridge = !self.bridges.is_empty();
+
+        if via_bridge {
+            info!(
+                "Criando cliente Tor com Arti via {} bridge(s) configurada(s)...",
+                self.bridges.len()
+            );
+        } else {
+            info!("Criando cliente Tor com Arti (conexão direta)...");
+        }
+
+        let tor_client = TorClient::<PreferredRuntime>::create_unbootstrapped(config)
+            .map_err(|e| TorError::ClientCreation(e.to_string()))?;
+
+        let mut events = tor_client.bootstrap_events();
+        let status_tx = self.status_tx.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                let pct = (event.as_frac() * 100.0).round().clamp(0.0, 100.0) as u8;
+                let status = if via_bridge {
+                    TorStatus::BootstrappingViaBridge(pct)
+                } else {
+                    TorStatus::Bootstrapping(pct)
+                };
+                let _ = status_tx.send(status);
+            }
+        });
+
+        self.set_status(if via_bridge {
+            TorStatus::BootstrappingViaBridge(0)
+        } else {
+            TorStatus::Bootstrapping(0)
+        });
+
+        if let Err(e) = tor_client.bootstrap().await {
+            let msg = e.to_string();
+            self.set_status(TorStatus::Error(msg.clone()));
+            return Err(TorError::BootstrapFailed(msg));
+        }
 
         self.tor_client = Some(Arc::new(tor_client));
-        self.status = TorStatus::Ready;
+        self.set_status(TorStatus::Ready);
         info!("Tor pronto para uso.");
         Ok(())
     }
 
+    /// Monta a `TorClientConfig`, registrando os pluggable transports das
+    /// bridges configuradas (obfs4, obfs3, snowflake) para que o Arti os invoque
+    /// durante o bootstrap.
+    fn build_client_config(&self) -> Result<TorClientConfig, TorError> {
+        let mut builder = TorClientConfig::builder();
+        let mut bridge_lines = Vec::with_capacity(self.bridges.len());
+
+        for bridge in &self.bridges {
+            let pt_name = match bridge.transport {
+                crate::config::PluggableTransport::Obfs4 => "obfs4",
+                crate::config::PluggableTransport::Obfs3 => "obfs3",
+                crate::config::PluggableTransport::Snowflake => "snowflake",
+            };
+
+            info!(
+                "Registrando bridge {} ({}) via transporte '{}'",
+                bridge.address, pt_name, pt_name
+            );
+
+            // Só bridges com `transport_binary` registram um transporte: o
+            // Arti invoca esse binário (obfs4proxy ou equivalente) para fazer
+            // o handshake antes de entregar a conexão ao cliente Tor. Sem
+            // binário, a linha da bridge é repassada ao Arti, mas nenhum
+            // transporte é registrado para ela (não há transporte in-process).
+            bridge_lines.push(format!(
+                "Bridge {} {} cert={} iat-mode={}",
+                pt_name, bridge.address, bridge.cert, bridge.iat_mode
+            ));
+
+            if let Some(binary) = &bridge.transport_binary {
+                builder
+                    .bridges()
+                    .transports()
+                    .push(pt_name.to_string(), vec![binary.clone()]);
+            }
+        }
+
+        if !bridge_lines.is_empty() {
+            builder.bridges().set_bridges(bridge_lines);
+        }
+
+        builder
+            .build()
+            .map_err(|e| TorError::ClientCreation(e.to_string()))
+    }
+
     /// Create an ephemeral hidden service (the main chat service)
     pub async fn create_hidden_service(&mut self) -> Result<String, TorError> {
         let tor_client = self.tor_client.as_ref()
@@ -152,14 +368,20 @@ impl TorManager {
         self.onion_address = Some(onion_address.clone());
         self._onion_service = Some(Arc::new(running));
 
-        // Processa requisições em background
-        let client_clone = tor_client.clone();
+        // Processa requisições em background, rodando o handshake e o loop
+        // de mensagens de cada conexão aceita em vez de apenas descartá-la.
+        let incoming_tx = self.incoming_tx.clone();
+        let local_identity = self.local_identity.clone();
         tokio::spawn(async move {
             while let Some(request) = requests.next().await {
                 info!("Nova conexão recebida no serviço principal");
-                if let Ok(mut stream) = request.accept().await {
-                    // Aqui você passaria a stream para o protocolo de chat
-                    drop(stream);
+                if let Ok(stream) = request.accept().await {
+                    match (incoming_tx.clone(), local_identity.clone()) {
+                        (Some(tx), Some(identity)) => {
+                            tokio::spawn(handle_incoming_stream(stream, "onion-peer".to_string(), identity, tx));
+                        }
+                        _ => info!("Nenhum handler/identidade registrado; conexão recebida e descartada."),
+                    }
                 }
             }
         });
@@ -168,12 +390,16 @@ impl TorManager {
         Ok(onion_address)
     }
 
-    /// Cria um serviço onion efêmero que serve o endereço permanente do usuário.
-    /// Retorna o endereço onion temporário e um handle que pode ser usado para encerrar o serviço.
+    /// Cria um serviço onion efêmero que serve o endereço permanente do
+    /// usuário e, opcionalmente, envia um arquivo (estilo OnionShare).
+    /// Retorna o endereço onion temporário, a chave do manifesto do arquivo
+    /// (quando `file` foi informado) e um handle que pode ser usado para
+    /// encerrar o serviço.
     pub async fn create_ephemeral_sharing_service(
         &self,
         permanent_address: &str,
-    ) -> Result<(String, Arc<RunningOnionService>), TorError> {
+        file: Option<FileShare>,
+    ) -> Result<(String, Option<String>, Arc<RunningOnionService>), TorError> {
         let tor_client = self.tor_client.as_ref()
             .ok_or(TorError::NotInitialized)?;
 
@@ -192,37 +418,331 @@ impl TorManager {
             .map_err(|e| TorError::HiddenServiceCreation(e.to_string()))?;
 
         let onion_address = running.onion_address().to_string();
+        let permanent_address = permanent_address.to_string();
 
-        // Cria o servidor HTTP com axum
-        let app = Router::new().route("/", get(|| async move {
-            permanent_address.to_string()
-        }));
+        // Quando há arquivo, gera uma chave de manifesto aleatória que nunca
+        // trafega pelo serviço onion: ela só é devolvida ao chamador, que a
+        // mostra ao usuário separada do link, para ser entregue por outro
+        // canal. Um link cujo
+        // endereço onion tenha sido trocado por um servidor malicioso ainda
+        // produziria um manifesto que não decifra sob essa chave, em vez de
+        // ser aceito silenciosamente com um digest diferente do original.
+        let manifest_key = file.as_ref().map(|_| {
+            use sodiumoxide::randombytes::randombytes;
+            // 32 bytes: o mesmo tamanho de chave usado em todo `crypto.rs`
+            // para `SessionKey` (ver `secretbox::KEYBYTES`/`derive_contacts_key`).
+            SessionKey::from_slice(&randombytes(32))
+                .expect("randombytes(32) sempre tem o tamanho esperado por SessionKey")
+        });
+        let manifest_key_b64 = manifest_key.as_ref().map(|k| BASE64.encode(k.as_ref()));
 
-        // Inicia o servidor na porta local usando hyper
-        let server_addr = SocketAddr::from(([127, 0, 0, 1], local_port));
-        tokio::spawn(async move {
-            if let Err(e) = hyper::Server::bind(&server_addr)
-                .serve(app.into_make_service())
-                .await
-            {
-                eprintln!("Erro no servidor hyper: {}", e);
+        let sealed_manifest = match (&file, &manifest_key) {
+            (Some(f), Some(key)) => {
+                let manifest = FileManifest {
+                    file_name: f.file_name.clone(),
+                    size: f.contents.len() as u64,
+                    sha3_256: f.sha3_256_hex.clone(),
+                };
+                let manifest_json = serde_json::to_vec(&manifest)
+                    .map_err(|e| TorError::HiddenServiceCreation(e.to_string()))?;
+                Some(Crypto::encrypt(&manifest_json, key))
             }
-        });
+            _ => None,
+        };
+        let file_contents = file.map(|f| Arc::new(f.contents));
 
-        // Processa requisições onion em background
-        let running_clone = running.clone();
+        // Serve o endereço permanente (e o arquivo, se houver) via HTTP
+        // sobre a substream "file" de cada stream onion aceita, multiplexada
+        // com `MuxConnection` em vez de rodar o hyper direto na conexão
+        // inteira. Isso deixa a mesma conexão onion livre para também
+        // carregar, por exemplo, uma substream de chat ou presença no
+        // futuro, sem precisar de um segundo serviço oculto. O servidor
+        // local que existia aqui antes nunca recebia tráfego: o SOCKS5/onion
+        // routing entrega as conexões através do próprio `requests`, não de
+        // um socket TCP local.
         tokio::spawn(async move {
             while let Some(request) = requests.next().await {
                 info!("Nova requisição recebida no serviço onion efêmero");
-                if let Ok(mut stream) = request.accept().await {
-                    // O hyper já está cuidando do HTTP, então não precisamos fazer nada aqui
-                    drop(stream);
+                if let Ok(stream) = request.accept().await {
+                    let permanent_address = permanent_address.clone();
+                    let sealed_manifest = sealed_manifest.clone();
+                    let file_contents = file_contents.clone();
+                    tokio::spawn(async move {
+                        let mux = crate::mux::MuxConnection::new(stream, false);
+                        let (name, file_stream) = match mux.accept_substream().await {
+                            Ok(accepted) => accepted,
+                            Err(e) => {
+                                error!("Falha ao aceitar substream no serviço onion efêmero: {}", e);
+                                return;
+                            }
+                        };
+                        if name != crate::mux::SUBSTREAM_FILE {
+                            error!("Substream inesperada '{}' no serviço onion efêmero (esperava '{}')", name, crate::mux::SUBSTREAM_FILE);
+                            return;
+                        }
+
+                        let service = hyper::service::service_fn(move |req: hyper::Request<hyper::Body>| {
+                            let permanent_address = permanent_address.clone();
+                            let sealed_manifest = sealed_manifest.clone();
+                            let file_contents = file_contents.clone();
+                            async move {
+                                let response = match req.uri().path() {
+                                    "/manifest" => match &sealed_manifest {
+                                        Some(sealed) => hyper::Response::new(hyper::Body::from(
+                                            serde_json::to_vec(sealed).unwrap_or_default(),
+                                        )),
+                                        None => hyper::Response::builder()
+                                            .status(hyper::StatusCode::NOT_FOUND)
+                                            .body(hyper::Body::empty())
+                                            .expect("resposta 404 estática sempre é válida"),
+                                    },
+                                    "/file" => match &file_contents {
+                                        Some(bytes) => hyper::Response::new(hyper::Body::from(bytes.as_ref().clone())),
+                                        None => hyper::Response::builder()
+                                            .status(hyper::StatusCode::NOT_FOUND)
+                                            .body(hyper::Body::empty())
+                                            .expect("resposta 404 estática sempre é válida"),
+                                    },
+                                    _ => hyper::Response::new(hyper::Body::from(permanent_address.clone())),
+                                };
+                                Ok::<_, hyper::Error>(response)
+                            }
+                        });
+                        if let Err(e) = Http::new().serve_connection(file_stream, service).await {
+                            error!("Erro ao servir HTTP no serviço onion efêmero: {}", e);
+                        }
+                    });
                 }
             }
         });
 
         info!("Serviço onion efêmero criado em: {}", onion_address);
-        Ok((onion_address, Arc::new(running)))
+        Ok((onion_address, manifest_key_b64, Arc::new(running)))
+    }
+
+    /// Conecta a um compartilhamento efêmero (ver `create_ephemeral_sharing_service`)
+    /// e baixa o arquivo anunciado em `/file`, validando-o contra o digest
+    /// SHA3-256 do manifesto em `/manifest` (decifrado com `manifest_key_b64`,
+    /// a chave entregue separadamente do link, nunca pelo próprio serviço
+    /// onion). O arquivo é gravado em `dest_path` à medida que chega, com o
+    /// digest recomputado durante o download; se ele não bater com o do
+    /// manifesto, o arquivo é removido e a função retorna `FileIntegrityMismatch`.
+    pub async fn download_shared_file(
+        &self,
+        onion_address: &str,
+        manifest_key_b64: &str,
+        dest_path: &std::path::Path,
+    ) -> Result<FileManifest, TorError> {
+        let manifest_key_bytes = BASE64.decode(manifest_key_b64)
+            .map_err(|e| TorError::HandshakeFailed(format!("chave de manifesto inválida: {e}")))?;
+        let manifest_key = SessionKey::from_slice(&manifest_key_bytes)
+            .ok_or_else(|| TorError::HandshakeFailed("chave de manifesto com tamanho inválido".to_string()))?;
+
+        let stream = self.connect_to_onion(onion_address, 8080).await?;
+        let mux = crate::mux::MuxConnection::new(stream, true);
+        let file_stream = mux.open_substream(crate::mux::SUBSTREAM_FILE).await
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+
+        let (mut sender, connection) = hyper::client::conn::handshake(file_stream).await
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                error!("Conexão HTTP com o compartilhamento efêmero encerrou com erro: {}", e);
+            }
+        });
+
+        let manifest_req = hyper::Request::builder()
+            .uri("/manifest")
+            .body(hyper::Body::empty())
+            .expect("requisição GET /manifest estática sempre é válida");
+        let manifest_body = hyper::body::to_bytes(
+            sender.send_request(manifest_req).await
+                .map_err(|e| TorError::ConnectionFailed(e.to_string()))?
+                .into_body(),
+        ).await.map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+
+        let sealed: EncryptedMessage = serde_json::from_slice(&manifest_body)
+            .map_err(|e| TorError::HandshakeFailed(format!("manifesto malformado: {e}")))?;
+        let manifest_json = Crypto::decrypt(&sealed, &manifest_key)
+            .map_err(|_| TorError::HandshakeFailed("manifesto não decifra com a chave do link; link adulterado?".to_string()))?;
+        let manifest: FileManifest = serde_json::from_slice(&manifest_json)
+            .map_err(|e| TorError::HandshakeFailed(format!("manifesto malformado: {e}")))?;
+
+        let file_req = hyper::Request::builder()
+            .uri("/file")
+            .body(hyper::Body::empty())
+            .expect("requisição GET /file estática sempre é válida");
+        let mut body = sender.send_request(file_req).await
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?
+            .into_body();
+
+        // Calcula o digest incrementalmente enquanto os blocos chegam da
+        // rede, em vez de gravar tudo e relê-lo depois para conferir.
+        let mut hasher = Sha3_256::new();
+        let mut out = File::create(dest_path).await
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+        while let Some(chunk) = hyper::body::HttpBody::data(&mut body).await {
+            let chunk = chunk.map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+            hasher.update(&chunk);
+            out.write_all(&chunk).await
+                .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+        }
+        out.flush().await.map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+
+        let actual = hex::encode(hasher.finalize());
+        if actual != manifest.sha3_256 {
+            let _ = tokio::fs::remove_file(dest_path).await;
+            return Err(TorError::FileIntegrityMismatch {
+                expected: manifest.sha3_256,
+                actual,
+            });
+        }
+
+        Ok(manifest)
+    }
+
+    /// Create (or restore) a hidden service with a stable, persistent
+    /// identity instead of a throwaway one. `nickname` selects which key in
+    /// the Tor client's onion service key store to use; calling this again
+    /// with the same `nickname` reuses the ed25519 v3 service key that store
+    /// holds for it, so the `.onion` address stays the same. `key_dir` is not
+    /// passed to Arti: this function only creates it and looks in it for the
+    /// `<nickname>.ed25519_seed` file used by the ownership check below.
+    pub async fn create_persistent_service(
+        &mut self,
+        nickname: &str,
+        key_dir: &str,
+    ) -> Result<String, TorError> {
+        let tor_client = self.tor_client.as_ref()
+            .ok_or(TorError::NotInitialized)?;
+
+        std::fs::create_dir_all(key_dir)
+            .map_err(|e| TorError::HiddenServiceCreation(e.to_string()))?;
+
+        info!(
+            "Carregando/criando identidade persistente '{}' em {}",
+            nickname, key_dir
+        );
+
+        let parsed_nickname = nickname.parse()
+            .map_err(|e| TorError::HiddenServiceCreation(format!("invalid nickname: {e}")))?;
+
+        let config = OnionServiceConfig::builder()
+            .nickname(parsed_nickname)
+            .build()
+            .map_err(|e| TorError::HiddenServiceCreation(e.to_string()))?;
+
+        // Arti resolve a chave ed25519 v3 do serviço a partir do keystore
+        // configurado no cliente Tor, indexado pelo nickname: se já existir
+        // uma chave para esse nickname, ela é reutilizada; caso contrário,
+        // uma nova é gerada. `key_dir` não faz parte dessa configuração.
+        let (running, mut requests) = tor_client.launch_onion_service(config).await
+            .map_err(|e| TorError::HiddenServiceCreation(e.to_string()))?;
+
+        let onion_address = running.onion_address().to_string();
+
+        // Confere que a chave que o keystore do Arti acabou de carregar (ou
+        // gerar) ainda corresponde ao endereço que ele está anunciando, para
+        // que uma chave corrompida ou trocada na pasta persistente falhe alto
+        // em vez de publicar um `.onion` diferente silenciosamente. A
+        // verificação lê o seed ed25519 bruto de `<nickname>.ed25519_seed`
+        // dentro de `key_dir`; nada neste código grava esse arquivo e o
+        // keystore do Arti não recebe `key_dir`, então, se o arquivo não
+        // existir, a verificação é pulada. Quando ele existe, ela roda tanto
+        // na primeira criação quanto em todo relançamento via
+        // `relaunch_persistent_service`, já que ambos passam por aqui.
+        let seed_path = PathBuf::from(key_dir).join(format!("{nickname}.ed25519_seed"));
+        if let Ok(seed_bytes) = std::fs::read(&seed_path) {
+            self.verify_address_ownership(&seed_bytes, &onion_address)?;
+        }
+
+        self.onion_address = Some(onion_address.clone());
+        self._onion_service = Some(Arc::new(running));
+        self.persistent_identity = Some((nickname.to_string(), PathBuf::from(key_dir)));
+
+        let incoming_tx = self.incoming_tx.clone();
+        let local_identity = self.local_identity.clone();
+        tokio::spawn(async move {
+            while let Some(request) = requests.next().await {
+                info!("Nova conexão recebida no serviço onion persistente");
+                if let Ok(stream) = request.accept().await {
+                    match (incoming_tx.clone(), local_identity.clone()) {
+                        (Some(tx), Some(identity)) => {
+                            tokio::spawn(handle_incoming_stream(stream, "onion-peer".to_string(), identity, tx));
+                        }
+                        _ => info!("Nenhum handler/identidade registrado; conexão recebida e descartada."),
+                    }
+                }
+            }
+        });
+
+        info!("Serviço onion persistente pronto: {}", onion_address);
+        Ok(onion_address)
+    }
+
+    /// Re-launch the last persistent identity created via
+    /// `create_persistent_service`, after `shutdown_hidden_service` tore the
+    /// running service down. Returns the same `.onion` address as before.
+    /// `create_persistent_service` itself runs the same key ownership check
+    /// on every load (skipped when the seed file is absent), so there is
+    /// nothing extra to check here.
+    pub async fn relaunch_persistent_service(&mut self) -> Result<String, TorError> {
+        let (nickname, key_dir) = self.persistent_identity.clone()
+            .ok_or_else(|| TorError::HiddenServiceCreation("no persistent identity configured".to_string()))?;
+        let key_dir = key_dir.to_string_lossy().to_string();
+        self.create_persistent_service(&nickname, &key_dir).await
+    }
+
+    /// Deriva o endereço onion v3 correspondente ao seed ed25519 `secret_key_bytes`
+    /// e confere que ele bate, caractere a caractere, com `claimed_address`.
+    /// Usado para detectar uma chave de identidade corrompida ou trocada
+    /// antes de tratá-la como dona de um endereço.
+    pub fn verify_address_ownership(
+        &self,
+        secret_key_bytes: &[u8],
+        claimed_address: &str,
+    ) -> Result<(), TorError> {
+        let seed = sign::Seed::from_slice(secret_key_bytes)
+            .ok_or_else(|| TorError::InvalidOnionAddress("seed ed25519 com tamanho inválido".to_string()))?;
+        let (public_key, _secret_key) = sign::keypair_from_seed(&seed);
+
+        let derived = onion_address_from_public_key(public_key.as_ref());
+        let claimed = claimed_address.trim_end_matches(".onion").to_lowercase();
+
+        if derived != claimed {
+            return Err(TorError::InvalidOnionAddress(format!(
+                "chave local corresponde a {derived}, não a {claimed}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Confirma que o circuito SOCKS5 realmente sai pela rede Tor, em vez de
+    /// apenas refletir que o bootstrap terminou. Monta um cliente `reqwest`
+    /// usando o proxy SOCKS5 do Arti e busca a página de verificação oficial
+    /// do Tor Project, procurando pela frase que ela só exibe quando o
+    /// tráfego de fato passou por um circuito Tor.
+    pub async fn verify_connectivity(&self) -> Result<bool, TorError> {
+        let socks_addr = self.socks_addr.ok_or(TorError::NotInitialized)?;
+        let proxy_url = format!("socks5h://{socks_addr}");
+
+        let proxy = reqwest::Proxy::all(&proxy_url)
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+        let client = reqwest::Client::builder()
+            .proxy(proxy)
+            .build()
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+
+        let body = client
+            .get("https://check.torproject.org")
+            .send()
+            .await
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+
+        Ok(body.contains("Congratulations. This browser is configured to use Tor."))
     }
 
     /// Connect to a remote onion service via SOCKS5
@@ -231,7 +751,7 @@ impl TorManager {
         address: &str,
         port: u16,
     ) -> Result<tokio::net::TcpStream, TorError> {
-        if self.status != TorStatus::Ready {
+        if self.get_status() != TorStatus::Ready {
             return Err(TorError::NotInitialized);
         }
         Self::validate_onion_address(address)?;
@@ -248,7 +768,7 @@ impl TorManager {
 
     /// Check if an address is reachable
     pub async fn is_reachable(&self, address: &str) -> bool {
-        if self.status != TorStatus::Ready || Self::validate_onion_address(address).is_err() {
+        if self.get_status() != TorStatus::Ready || Self::validate_onion_address(address).is_err() {
             return false;
         }
         let socks_addr = match self.socks_addr {
@@ -261,7 +781,9 @@ impl TorManager {
         )
     }
 
-    /// Shutdown the hidden service (drop the runner)
+    /// Shutdown the hidden service (drop the runner). If it was a persistent
+    /// identity, the nickname/key directory are kept so
+    /// `relaunch_persistent_service` can bring back the same address later.
     pub fn shutdown_hidden_service(&mut self) {
         self._onion_service = None;
         self.onion_address = None;
@@ -275,6 +797,125 @@ impl Default for TorManager {
     }
 }
 
+/// Roda o lado servidor do protocolo de chat sobre uma stream onion aceita:
+/// handshake autenticado (`protocol::run_server_handshake`, o mesmo que
+/// `conversar` roda do lado cliente via `run_client_handshake`), depois um
+/// loop que reconstrói frames fragmentados, valida checksums, decifra o
+/// conteúdo das mensagens e encaminha o resultado para `tx`. Retorna
+/// (encerrando a conexão) assim que a stream falhar, fechar, ou chegar uma
+/// mensagem `Disconnect`.
+async fn handle_incoming_stream<S>(
+    mut stream: S,
+    remote_address: String,
+    identity: crypto::IdentityKeyPair,
+    tx: mpsc::Sender<(P2PConnection, Message)>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut protocol = ChatProtocol::new();
+
+    let outcome = match crate::protocol::run_server_handshake(&mut stream, &protocol, &identity, None).await {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            error!("Handshake com {} falhou: {}", remote_address, e);
+            return;
+        }
+    };
+    let session_key = outcome.session_keys.rx.expose().clone();
+
+    let mut connection = P2PConnection::new(remote_address.clone());
+    connection.state = ConnectionState::Connected;
+    connection.session_key = Some(session_key.clone());
+
+    loop {
+        let frame = match read_frame(&mut stream).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                info!("Conexão {} encerrada pelo par.", remote_address);
+                return;
+            }
+            Err(e) => {
+                error!("Erro lendo frame de {}: {}", remote_address, e);
+                return;
+            }
+        };
+
+        let payload = match protocol.push_fragment(frame) {
+            Ok(Some(payload)) => payload,
+            Ok(None) => continue, // fragmento intermediário, aguarda o restante
+            Err(e) => {
+                error!("Frame inválido recebido de {}: {}", remote_address, e);
+                return;
+            }
+        };
+
+        let mut message = match protocol.deserialize_message(&payload) {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Mensagem malformada recebida de {}: {}", remote_address, e);
+                return;
+            }
+        };
+
+        if message.msg_type == MessageType::Disconnect {
+            info!("{} solicitou desconexão.", remote_address);
+            return;
+        }
+
+        if matches!(message.msg_type, MessageType::Text | MessageType::File) {
+            match decrypt_content(&message.content, &session_key) {
+                Ok(plaintext) => message.content = plaintext,
+                Err(e) => {
+                    error!("Falha ao decifrar mensagem de {}: {}", remote_address, e);
+                    continue;
+                }
+            }
+        }
+
+        if tx.send((connection.clone(), message)).await.is_err() {
+            info!("Handler de mensagens encerrado; encerrando conexão com {}.", remote_address);
+            return;
+        }
+    }
+}
+
+/// Versão do formato de endereço onion v3 (fixa pelo protocolo Tor).
+const ONION_V3_VERSION: u8 = 3;
+
+/// Deriva o endereço onion v3 (sem o sufixo `.onion`, em minúsculas) de uma
+/// chave pública ed25519, seguindo o formato do rend-spec-v3: os 32 bytes da
+/// chave pública, seguidos de um checksum de 2 bytes (os 2 primeiros bytes
+/// de `SHA3-256(".onion checksum" || chave_pública || versão)`) e do byte de
+/// versão, tudo codificado em base32 sem padding.
+fn onion_address_from_public_key(public_key: &[u8]) -> String {
+    let mut checksum_input = Vec::with_capacity(15 + public_key.len() + 1);
+    checksum_input.extend_from_slice(b".onion checksum");
+    checksum_input.extend_from_slice(public_key);
+    checksum_input.push(ONION_V3_VERSION);
+
+    let mut hasher = Sha3_256::new();
+    hasher.update(&checksum_input);
+    let digest = hasher.finalize();
+
+    let mut addr_bytes = Vec::with_capacity(public_key.len() + 3);
+    addr_bytes.extend_from_slice(public_key);
+    addr_bytes.extend_from_slice(&digest[..2]);
+    addr_bytes.push(ONION_V3_VERSION);
+
+    BASE32_NOPAD.encode(&addr_bytes).to_lowercase()
+}
+
+/// Decifra o campo `content` de uma `Message` (um `EncryptedMessage`
+/// serializado em JSON) usando a chave de sessão da conexão.
+fn decrypt_content(content: &str, key: &SessionKey) -> Result<String, TorError> {
+    let enc: EncryptedMessage = serde_json::from_str(content)
+        .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+    let plaintext = Crypto::decrypt(&enc, key)
+        .map_err(|e| TorError::ConnectionFailed(e.to_string()))?;
+    String::from_utf8(plaintext)
+        .map_err(|e| TorError::ConnectionFailed(e.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,4 +943,114 @@ mod tests {
         let addr = "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567891";
         assert!(TorManager::validate_onion_address(addr).is_err());
     }
+
+    #[test]
+    fn test_verify_address_ownership_accepts_matching_address() {
+        sodiumoxide::init().ok();
+        let (public_key, secret_key) = sign::gen_keypair();
+        let address = onion_address_from_public_key(public_key.as_ref());
+        let manager = TorManager::new();
+
+        let seed = &secret_key.as_ref()[..sign::SEEDBYTES];
+        assert!(manager.verify_address_ownership(seed, &format!("{address}.onion")).is_ok());
+    }
+
+    #[test]
+    fn test_verify_address_ownership_rejects_mismatched_address() {
+        sodiumoxide::init().ok();
+        let (_public_key, secret_key) = sign::gen_keypair();
+        let manager = TorManager::new();
+
+        let seed = &secret_key.as_ref()[..sign::SEEDBYTES];
+        let bogus = "a".repeat(56);
+        assert!(manager.verify_address_ownership(seed, &bogus).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_share_computes_digest_incrementally_while_reading() {
+        let path = std::env::temp_dir().join(format!("torpaste-fileshare-test-{}.bin", std::process::id()));
+        tokio::fs::write(&path, b"conteudo de teste do compartilhamento").await.unwrap();
+
+        let share = FileShare::from_path(&path).await.unwrap();
+        assert_eq!(share.file_name, path.file_name().unwrap().to_string_lossy());
+        assert_eq!(share.contents, b"conteudo de teste do compartilhamento".to_vec());
+
+        let mut hasher = Sha3_256::new();
+        hasher.update(&share.contents);
+        assert_eq!(share.sha3_256_hex, hex::encode(hasher.finalize()));
+
+        tokio::fs::remove_file(&path).await.ok();
+    }
+
+    #[tokio::test]
+    async fn test_sealed_manifest_roundtrips_and_rejects_wrong_key() {
+        sodiumoxide::init().ok();
+        let manifest = FileManifest {
+            file_name: "relatorio.pdf".to_string(),
+            size: 1234,
+            sha3_256: "deadbeef".to_string(),
+        };
+        let key = SessionKey::from_slice(&[7u8; 32]).unwrap();
+        let sealed = Crypto::encrypt(&serde_json::to_vec(&manifest).unwrap(), &key);
+
+        let opened = Crypto::decrypt(&sealed, &key).unwrap();
+        let reopened: FileManifest = serde_json::from_slice(&opened).unwrap();
+        assert_eq!(reopened.sha3_256, manifest.sha3_256);
+
+        // Uma chave de manifesto adulterada não consegue decifrar o
+        // manifesto selado pelo remetente.
+        let wrong_key = SessionKey::from_slice(&[9u8; 32]).unwrap();
+        assert!(Crypto::decrypt(&sealed, &wrong_key).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_read_frame_roundtrip() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let protocol = ChatProtocol::new();
+        let frame = protocol.create_frame("hello via frame".to_string());
+
+        write_frame(&mut client, &frame).await.unwrap();
+        let received = read_frame(&mut server).await.unwrap().unwrap();
+        assert_eq!(received.payload, "hello via frame");
+    }
+
+    #[tokio::test]
+    async fn test_full_handshake_and_message_flow() {
+        Crypto::init();
+        let (mut client, server) = tokio::io::duplex(8192);
+        let (tx, mut rx) = mpsc::channel(1);
+
+        let server_identity = Crypto::generate_identity();
+        let server_task = tokio::spawn(handle_incoming_stream(
+            server, "peer.onion".to_string(), server_identity, tx,
+        ));
+
+        // Lado cliente: roda o mesmo handshake autenticado que `conversar`
+        // roda de verdade contra um serviço onion, em vez do antigo
+        // `HandshakeMessage` não autenticado.
+        let client_identity = Crypto::generate_identity();
+        let client_protocol = ChatProtocol::new();
+        let outcome = crate::protocol::run_client_handshake(&mut client, &client_protocol, &client_identity, None)
+            .await
+            .unwrap();
+
+        // Manda uma mensagem de texto cifrada com a chave tx do cliente (que
+        // deve bater com a chave rx que o servidor derivou do outro lado).
+        let encrypted = Crypto::encrypt(b"oi servidor", outcome.session_keys.tx.expose());
+        let mut message_protocol = ChatProtocol::new();
+        let message = message_protocol.create_text_message(
+            "client.onion",
+            serde_json::to_string(&encrypted).unwrap(),
+        );
+        let message_payload = serde_json::to_string(&message).unwrap();
+        for frame in message_protocol.fragment_message(message_payload) {
+            write_frame(&mut client, &frame).await.unwrap();
+        }
+
+        let (_connection, received_message) = rx.recv().await.unwrap();
+        assert_eq!(received_message.content, "oi servidor");
+
+        drop(client);
+        let _ = server_task.await;
+    }
 }